@@ -15,7 +15,107 @@ pub struct ImageProcess {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ImageBaizheng {
     pub page_number_diff: f32,
-    pub model_point_wh_cosine_similarity: f32
+    pub model_point_wh_cosine_similarity: f32,
+    /// 查找全部定位点时，以预测位置为中心的搜索半径，为定位点高度的倍数
+    pub model_point_search_radius: f32,
+    /// 同一行或同一列相邻两段定位点连线的夹角超过此角度（度）认为纸张被折过
+    pub fold_angle: f32,
+    /// 候选定位点形状、实心程度、填涂率三项得分乘积的下限
+    pub mark_min_score: f32,
+    /// 定位点拟合的缩放和按图片宽度估计的缩放允许相差的比例
+    pub mark_scale_tolerance: f32,
+    /// 定位点拟合允许的最大偏转角度（度）
+    pub mark_max_angle: f32,
+    /// 定位点离拟合位置在此距离内算吻合，为定位点高度的倍数
+    pub mark_inlier_distance: f32,
+    /// 至少吻合的定位点个数
+    pub mark_min_inliers: usize,
+    /// 作为对角线两端的候选定位点个数上限，按得分从高到低取
+    pub mark_max_candidates: usize,
+    /// 相似变换之后用透视变换重新拟合定位点的最多次数
+    pub mark_refine_iterations: usize,
+    /// 拟合残差超过定位点短边的此倍数认为拟合不可信
+    pub mark_max_residual: f32,
+    /// 四个角定位点围成区域的宽高比和模板允许相差的比例
+    pub location_aspect_tolerance: f32,
+    /// 图片长宽比和模板的对数差，不旋转和旋转90度两者相差超过此值才只试一种方向
+    pub orientation_aspect_margin: f32
+}
+
+/// 填涂识别参数
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BlackFill {
+    /// 整页无法自适应分割时使用的固定阈值
+    pub fill_rate_threshold: f32,
+    /// 自适应分割出的空白和填涂两类均值至少相差多少才可信
+    pub min_cluster_distance: f32,
+    /// 以整页空白和填涂的平均填涂率为0和1，低于此比例为空白
+    pub erased_ratio: f32,
+    /// 低于此比例为擦除或浅涂
+    pub ambiguous_ratio: f32,
+    /// 低于此比例为存疑，否则为填涂
+    pub filled_ratio: f32,
+    /// 页码点标注填涂率不低于此值视为应填涂，用来检验自适应分界
+    pub page_number_filled_rate: f32
+}
+
+/// 填涂选项状态
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FillStatus {
+    pub blank: u8,
+    pub filled: u8,
+    pub erased: u8,
+    pub ambiguous: u8
+}
+
+/// 勾叉识别参数
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Vx {
+    /// 忽略框四周的比例，避开印刷边框
+    pub border_ignore: f32,
+    /// 笔迹像素占比低于此为空白
+    pub blank_rate: f32,
+    /// 笔画外多余笔迹占比达到此值视为半对的短斜线
+    pub half_tick_residual: f32
+}
+
+/// 勾叉识别结果类型
+#[derive(Debug, Deserialize, Serialize)]
+pub struct VxType {
+    pub blank: u8,
+    pub tick: u8,
+    pub cross: u8,
+    pub half_tick: u8
+}
+
+/// 数字识别参数
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Number {
+    /// 忽略框四周的比例，避开印刷边框
+    pub border_ignore: f32,
+    /// 笔迹像素占比低于此为空白
+    pub blank_rate: f32,
+    /// 像素数少于此的连通域当作噪点
+    pub min_component_pixels: usize,
+    /// 闭合区域个数不符合数字写法时加的距离
+    pub hole_penalty: f32,
+    /// 宽高比低于此的字符直接认为是1
+    pub narrow_ratio: f32,
+    /// 认为是1的字符笔迹占外接框的比例低于此时置信度为0
+    pub narrow_min_fill: f32
+}
+
+/// 条码识别重试参数
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Barcode {
+    /// 放大重试的倍数
+    pub upscale: u32,
+    /// 锐化重试的高斯半径
+    pub unsharpen_sigma: f32,
+    /// 锐化重试的差值阈值
+    pub unsharpen_threshold: i32,
+    /// 扩大截取范围重试时，每边扩大框宽高的比例
+    pub widen_ratio: f32
 }
 
 /// 识别类型参数
@@ -29,12 +129,70 @@ pub struct RecognitionType {
     pub qrcode: u8
 }
 
+/// 选择题类型参数，对应scanjson中的options_type
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChoiceType {
+    pub single: u8,
+    pub judge: u8,
+    pub multiple: u8
+}
+
+/// 考号每列识别状态码
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExamNumberCode {
+    pub succeeded: u8,
+    pub blank: u8,
+    pub multiple: u8,
+    pub ambiguous: u8
+}
+
+/// 空白页判断参数
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BlankPage {
+    /// 忽略四周的比例，避开扫描仪边缘阴影
+    pub border_ignore: f32,
+    /// 平均灰度高于此为空白页
+    pub min_mean_intensity: f32
+}
+
+/// 输入图片的状态码
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ImageStatusCode {
+    pub succeeded: u8,
+    pub unmatched: u8,
+    pub location_failed: u8,
+    /// 和已匹配的图片是同一页
+    pub duplicate: u8,
+    /// 空白图片
+    pub blank: u8
+}
+
+/// 一张图上多页的拆分参数
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ImageSplit {
+    /// 最多拆成几份
+    pub max_parts: u8,
+    /// 每份和模板长宽比的对数差在此范围内才尝试拆分
+    pub aspect_tolerance: f32
+}
+
 /// 配置参数
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
     pub image_process: ImageProcess,
     pub image_baizheng: ImageBaizheng,
+    pub black_fill: BlackFill,
+    pub fill_status: FillStatus,
+    pub vx: Vx,
+    pub vx_type: VxType,
+    pub number: Number,
+    pub barcode: Barcode,
     pub recognize_type: RecognitionType,
+    pub choice_type: ChoiceType,
+    pub exam_number_code: ExamNumberCode,
+    pub image_status_code: ImageStatusCode,
+    pub blank_page: BlankPage,
+    pub image_split: ImageSplit,
     // 其他配置参数
 }
 
@@ -57,7 +215,58 @@ pub static CONFIG: Config = Config{
     },
     image_baizheng: ImageBaizheng{
         page_number_diff: 0.21,
-        model_point_wh_cosine_similarity: 0.985
+        model_point_wh_cosine_similarity: 0.985,
+        model_point_search_radius: 3.0,
+        fold_angle: 1.0,
+        mark_min_score: 0.5,
+        mark_scale_tolerance: 0.3,
+        mark_max_angle: 15.0,
+        mark_inlier_distance: 1.0,
+        mark_min_inliers: 4,
+        mark_max_candidates: 40,
+        mark_refine_iterations: 3,
+        mark_max_residual: 1.0,
+        location_aspect_tolerance: 0.1,
+        orientation_aspect_margin: 0.2
+    },
+    black_fill: BlackFill{
+        fill_rate_threshold: 0.5,
+        min_cluster_distance: 0.3,
+        erased_ratio: 0.15,
+        ambiguous_ratio: 0.4,
+        filled_ratio: 0.6,
+        page_number_filled_rate: 0.5
+    },
+    fill_status: FillStatus{
+        blank: 0,
+        filled: 1,
+        erased: 2,
+        ambiguous: 3
+    },
+    vx: Vx{
+        border_ignore: 0.08,
+        blank_rate: 0.01,
+        half_tick_residual: 0.15
+    },
+    vx_type: VxType{
+        blank: 0,
+        tick: 1,
+        cross: 2,
+        half_tick: 3
+    },
+    number: Number{
+        border_ignore: 0.08,
+        blank_rate: 0.01,
+        min_component_pixels: 10,
+        hole_penalty: 1.5,
+        narrow_ratio: 0.35,
+        narrow_min_fill: 0.5
+    },
+    barcode: Barcode{
+        upscale: 2,
+        unsharpen_sigma: 1.0,
+        unsharpen_threshold: 5,
+        widen_ratio: 0.2
     },
     recognize_type: RecognitionType{
         black_fill: 1,
@@ -66,5 +275,31 @@ pub static CONFIG: Config = Config{
         qrcode: 4,
         barcode: 5,
         coordinate: 6,
+    },
+    choice_type: ChoiceType{
+        single: 1,
+        judge: 2,
+        multiple: 3,
+    },
+    exam_number_code: ExamNumberCode{
+        succeeded: 0,
+        blank: 1,
+        multiple: 2,
+        ambiguous: 3,
+    },
+    image_status_code: ImageStatusCode{
+        succeeded: 0,
+        unmatched: 1,
+        location_failed: 2,
+        duplicate: 3,
+        blank: 4,
+    },
+    blank_page: BlankPage{
+        border_ignore: 0.05,
+        min_mean_intensity: 253.0,
+    },
+    image_split: ImageSplit{
+        max_parts: 3,
+        aspect_tolerance: 0.1,
     }
 };
\ No newline at end of file
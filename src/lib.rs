@@ -18,6 +18,7 @@ mod tests {
     use super::*;
     use models::scan_json::{InputScan,InputImage};
     use recognition::engine::Engine;
+    use config::CONFIG;
 
     #[test]
     fn test_demo() -> Result<()> {
@@ -37,7 +38,10 @@ mod tests {
         let (output, imgs_and_model_points) = engine.recognize(&input_images);
 
 
-        let out_json_path = format!("dev/test_data/{test_id}.json");
+        // 输出写到临时目录，不弄脏测试数据
+        let out_dir = std::env::temp_dir().join(format!("card_rec_{test_id}"));
+        fs::create_dir_all(&out_dir)?;
+        let out_json_path = out_dir.join(format!("{test_id}.json"));
         let mut file = File::create(out_json_path)?;
         serde_json::to_writer(&mut file, &output)?;
 
@@ -46,13 +50,13 @@ mod tests {
             if matches!(img_and_model_points, None){continue;}
             if matches!(page.image_rendering, None){continue;}
             let mut rendering = trans_base64_to_image(&page.image_rendering.expect("image_rendering is None"));
-            let out_img_path = format!("dev/test_data/output_view_{index}.jpg");
+            let out_img_path = out_dir.join(format!("output_view_{index}.jpg"));
             rendering.to_rgb8().save(out_img_path);
         }
 
         for (index,img) in output.images.iter().enumerate(){
             let img = trans_base64_to_image(&img.image_source);
-            let out_img_path = format!("dev/test_data/output_view_image_status_{index}.jpg");
+            let out_img_path = out_dir.join(format!("output_view_image_status_{index}.jpg"));
             img.to_rgb8().save(out_img_path);
         }
 
@@ -60,8 +64,360 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_two_cluster_split() {
+        // 淡铅笔填涂也能和空白分开
+        let fill_rates = [0.02, 0.0, 0.05, 0.45, 0.5, 0.03, 0.55, 0.01];
+        let (blank, filled) = my_utils::math::two_cluster_split(&fill_rates).expect("Split Failed");
+        assert!(blank < 0.05);
+        assert!((filled - 0.5).abs() < 1e-4);
+        // 只有一种取值无法分类
+        assert!(my_utils::math::two_cluster_split(&[0.3, 0.3, 0.3]).is_none());
+    }
+
+
+    #[test]
+    fn test_homography() {
+        // 梯形畸变的四个角
+        let model = [(0.0, 0.0), (1000.0, 0.0), (0.0, 700.0), (1000.0, 700.0)];
+        let real = [(120.0, 80.0), (2900.0, 40.0), (60.0, 2050.0), (3000.0, 2100.0)];
+        let homography = my_utils::math::solve_homography(&model, &real).expect("Solve Failed");
+        for (from, to) in model.iter().zip(real.iter()) {
+            let (x, y) = my_utils::math::apply_homography(&homography, *from).expect("Apply Failed");
+            assert!((x - to.0).abs() < 0.5 && (y - to.1).abs() < 0.5);
+        }
+        // 三点共线无解
+        let line = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (0.0, 5.0)];
+        assert!(my_utils::math::solve_homography(&line, &real).is_none());
+    }
+
+
+    #[test]
+    fn test_hungarian() {
+        // 逐行取最小会让第二行没有可选的列，整体最优是交叉分配
+        let cost = vec![
+            vec![0.1, 0.2, 1000.0],
+            vec![0.15, 1000.0, 1000.0],
+        ];
+        assert_eq!(my_utils::math::hungarian(&cost), vec![Some(1), Some(0)]);
+        // 行多于列时差的行不分配
+        let cost = vec![vec![0.3], vec![0.1], vec![0.2]];
+        assert_eq!(my_utils::math::hungarian(&cost), vec![None, Some(0), None]);
+    }
+
+    #[test]
+    fn test_group_by_code() {
+        // 同一模板的两份答题卡各自成组，反面跟随正面，未知码和它的反面不识别
+        let codes = vec![
+            Some("exam_a".to_string()), None,
+            Some("exam_a".to_string()), None,
+            Some("unknown".to_string()), None,
+            Some("exam_b".to_string()),
+        ];
+        let (groups, unmatched) = recognition::registry::group_by_code(&codes, |code| code.starts_with("exam"));
+        assert_eq!(groups, vec![
+            ("exam_a".to_string(), vec![0, 1]),
+            ("exam_a".to_string(), vec![2, 3]),
+            ("exam_b".to_string(), vec![6]),
+        ]);
+        assert_eq!(unmatched, vec![4, 5]);
+    }
+
+
+    #[test]
+    fn test_split_two_up() {
+        // 两页并排拼成一张，再转90度模拟竖着扫描的A3
+        let test_id = "194751";
+        let input_scan = read_json(&format!("dev/test_data/cards/{test_id}/scan.json"));
+        let mut input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        let pages: Vec<image::RgbImage> = input_images.images.iter()
+            .map(|base64_image| {
+                let img = trans_base64_to_image(base64_image);
+                if img.width() < img.height() { img.rotate90() } else { img }.to_rgb8()
+            })
+            .collect();
+        let (w, h) = (pages[0].width() + pages[1].width(), pages[0].height().max(pages[1].height()));
+        let mut two_up = image::RgbImage::from_pixel(w, h, image::Rgb([255, 255, 255]));
+        image::imageops::overlay(&mut two_up, &pages[0], 0, 0);
+        image::imageops::overlay(&mut two_up, &pages[1], pages[0].width() as i64, 0);
+        let two_up = image::imageops::rotate90(&two_up);
+        input_images.images = vec![my_utils::image::image_to_base64(&two_up)];
+
+        let engine = Engine::new(input_scan);
+        let (output, _) = engine.recognize(&input_images);
+        assert_eq!(output.images.len(), 2);
+        for (part, status) in output.images.iter().enumerate() {
+            assert_eq!(status.code, 0);
+            assert_eq!(status.input_index, 0);
+            assert_eq!(status.part, Some(part));
+        }
+        for page in output.pages.iter() {
+            let source = page.source.as_ref().expect("page is not matched");
+            assert_eq!((source.input_index, source.parts), (0, 2));
+        }
+        assert_eq!(output.exam_number.and_then(|exam_number| exam_number.value), Some("21112".to_string()));
+    }
+
+    #[test]
+    fn test_split_single_page() {
+        // 正常的单页不拆分
+        let test_id = "193558";
+        let input_scan = read_json(&format!("dev/test_data/cards/{test_id}/scan.json"));
+        let input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        let engine = Engine::new(input_scan);
+        let (output, _) = engine.recognize(&input_images);
+        assert_eq!(output.images.len(), 1);
+        assert_eq!(output.images[0].part, None);
+        let source = output.pages[0].source.as_ref().expect("page is not matched");
+        assert_eq!((source.input_index, source.parts, source.part), (0, 1, 0));
+    }
+
+    #[test]
+    fn test_duplicate() {
+        // 同一张图上传两次，先上传的匹配page，后上传的标为重复
+        let test_id = "193558";
+        let input_scan = read_json(&format!("dev/test_data/cards/{test_id}/scan.json"));
+        let mut input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        input_images.images.push(input_images.images[0].clone());
+        let engine = Engine::new(input_scan);
+        let (output, _) = engine.recognize(&input_images);
+        assert_eq!(output.images.len(), 2);
+        assert_eq!(output.images[0].code, CONFIG.image_status_code.succeeded);
+        assert_eq!(output.images[0].duplicate_of, None);
+        assert_eq!(output.images[1].code, CONFIG.image_status_code.duplicate);
+        assert_eq!(output.images[1].duplicate_of, Some(0));
+        let source = output.pages[0].source.as_ref().expect("page is not matched");
+        assert_eq!(source.input_index, 0);
+    }
+
+
+    #[test]
+    fn test_choice() {
+        // 用考号填涂区的格子拼出一道选择题，A和B填涂，C空白
+        use models::scan_json::{Item, Recognition, Value as ItemValue};
+        use models::rec_result::Value;
+        let test_id = "194751";
+        let mut input_scan = read_json(&format!("dev/test_data/cards/{test_id}/scan.json"));
+        let input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        let columns = input_scan.pages[0].exam_number.clone().expect("examNumber is None");
+        let choice = |options_type: u8| Recognition{
+            rec_id: format!("choice_{options_type}"),
+            rec_type: CONFIG.recognize_type.black_fill,
+            options: [(0, 2, "A"), (1, 1, "B"), (0, 0, "C")].into_iter()
+                .map(|(column, index, value)| Item{
+                    value: Some(ItemValue::String(value.to_string())),
+                    coordinate: columns[column].options[index].coordinate,
+                })
+                .collect(),
+            options_type: Some(options_type),
+        };
+        let choice_types = [CONFIG.choice_type.multiple, CONFIG.choice_type.single, CONFIG.choice_type.judge];
+        input_scan.pages[0].recognizes.extend(choice_types.map(choice));
+        let engine = Engine::new(input_scan);
+        let (output, _) = engine.recognize(&input_images);
+        let result = |options_type: u8| {
+            let rec_out = output.pages[0].recognizes.iter()
+                .find(|rec_out| rec_out.rec_id == format!("choice_{options_type}"))
+                .expect("choice is missing");
+            let flags: Vec<i32> = rec_out.rec_options.iter()
+                .map(|option_out| match option_out.value {
+                    Some(Value::Integer(flag)) => flag,
+                    _ => panic!("option flag is missing"),
+                })
+                .collect();
+            (rec_out.rec_value.as_ref().map(|value| value.to_string()), flags)
+        };
+
+        // 多选题输出所有填涂的选项
+        assert_eq!(result(CONFIG.choice_type.multiple), (Some("AB".to_string()), vec![1, 1, 0]));
+        // 单选题涂了多个只输出最黑的一个
+        let (value, flags) = result(CONFIG.choice_type.single);
+        assert!(matches!(value.as_deref(), Some("A") | Some("B")));
+        assert_eq!(flags.iter().sum::<i32>(), 1);
+        assert_eq!(flags[2], 0);
+        // 判断题两个都涂不输出
+        assert_eq!(result(CONFIG.choice_type.judge), (None, vec![0, 0, 0]));
+    }
+
+    #[test]
+    fn test_exam_number() {
+        let test_id = "194751";
+        let input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        let engine = Engine::new(read_json(&format!("dev/test_data/cards/{test_id}/scan.json")));
+        let (output, _) = engine.recognize(&input_images);
+        let exam_number = output.exam_number.expect("exam_number is None");
+        assert_eq!(exam_number.value, Some("21112".to_string()));
+        assert!(exam_number.columns.iter().all(|column| column.code == CONFIG.exam_number_code.succeeded));
+
+        // 第一列多加一个第二列填涂的格子，第二列去掉填涂的格子
+        let mut input_scan = read_json(&format!("dev/test_data/cards/{test_id}/scan.json"));
+        let columns = input_scan.pages[0].exam_number.as_mut().expect("examNumber is None");
+        let filled = columns[1].options.remove(1);
+        columns[0].options.push(filled);
+        let engine = Engine::new(input_scan);
+        let (output, _) = engine.recognize(&input_images);
+        let exam_number = output.exam_number.expect("exam_number is None");
+        assert_eq!(exam_number.value, None);
+        let codes: Vec<u8> = exam_number.columns.iter().map(|column| column.code).collect();
+        let code = &CONFIG.exam_number_code;
+        assert_eq!(codes, vec![code.multiple, code.blank, code.succeeded, code.succeeded, code.succeeded]);
+        assert_eq!(exam_number.columns[2].value, Some("1".to_string()));
+    }
 
-    fn read_json(json_path: &str) -> InputScan {
+    #[test]
+    fn test_image_status() {
+        // 正常的两页之后加一张白纸和一张其他模板的答题卡
+        let test_id = "194751";
+        let mut input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        let blank = image::RgbImage::from_pixel(1200, 850, image::Rgb([255, 255, 255]));
+        input_images.images.push(my_utils::image::image_to_base64(&blank));
+        let other = read_image("dev/test_data/cards/193558/images").expect("Read Image Failed");
+        input_images.images.extend(other.images);
+        let engine = Engine::new(read_json(&format!("dev/test_data/cards/{test_id}/scan.json")));
+        let (output, _) = engine.recognize(&input_images);
+        let code = &CONFIG.image_status_code;
+        let statuses: Vec<(usize, u8)> = output.images.iter().map(|status| (status.input_index, status.code)).collect();
+        assert_eq!(statuses, vec![(0, code.succeeded), (1, code.succeeded), (2, code.blank), (3, code.unmatched)]);
+        assert!(output.images[2].mean_intensity > CONFIG.blank_page.min_mean_intensity);
+        assert!(output.images[2..].iter().all(|status| status.page_index.is_none()));
+        assert!(output.pages.iter().all(|page| page.has_page));
+    }
+
+    type Transform = fn(&image::DynamicImage) -> image::DynamicImage;
+
+    /// 每张图片做同样的变换
+    fn transform_images(input_images: &InputImage, transform: Transform) -> InputImage {
+        let images = input_images.images.iter()
+            .map(|base64_image| my_utils::image::image_to_base64(&transform(&trans_base64_to_image(base64_image)).to_rgb8()))
+            .collect();
+        InputImage{task_id: input_images.task_id.clone(), images, calling_type: input_images.calling_type}
+    }
+
+    #[test]
+    fn test_orientation() {
+        // 原图第一张正向，第二张需要转270度，转180度和左右镜像后都能摆正
+        let test_id = "194751";
+        let input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        let engine = Engine::new(read_json(&format!("dev/test_data/cards/{test_id}/scan.json")));
+        let cases: [(Transform, [i32; 2], bool); 2] = [
+            (image::DynamicImage::rotate180, [180, 90], false),
+            (image::DynamicImage::fliph, [0, 90], true),
+        ];
+        for (transform, orientations, mirrored) in cases {
+            let (output, _) = engine.recognize(&transform_images(&input_images, transform));
+            assert_eq!(output.images.len(), 2);
+            for (status, orientation) in output.images.iter().zip(orientations) {
+                assert_eq!(status.code, CONFIG.image_status_code.succeeded);
+                assert_eq!((status.orientation, status.mirrored), (Some(orientation), Some(mirrored)));
+            }
+            assert_eq!(output.exam_number.and_then(|exam_number| exam_number.value), Some("21112".to_string()));
+        }
+    }
+
+    /// 模拟手机拍照的梯形畸变，上边两端各向内收k倍宽度，k为负时收下边
+    fn warp_keystone(input_images: &InputImage, k: f32) -> InputImage {
+        use imageproc::geometric_transformations::{warp, Interpolation, Projection};
+        let images = input_images.images.iter()
+            .map(|base64_image| {
+                let img = trans_base64_to_image(base64_image).to_rgb8();
+                let (w, h) = (img.width() as f32, img.height() as f32);
+                let (top, bottom) = if k >= 0.0 { (k * w, 0.0) } else { (0.0, -k * w) };
+                let projection = Projection::from_control_points(
+                    [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)],
+                    [(top, 0.0), (w - top, 0.0), (bottom, h), (w - bottom, h)],
+                ).expect("Projection Failed");
+                let warped = warp(&img, &projection, Interpolation::Bilinear, image::Rgb([255, 255, 255]));
+                my_utils::image::image_to_base64(&warped)
+            })
+            .collect();
+        InputImage{task_id: input_images.task_id.clone(), images, calling_type: input_images.calling_type}
+    }
+
+    #[test]
+    fn test_keystone() {
+        // 上下边长相差6%，缺的角按同行同列的定位点预测，所有页都能匹配
+        let test_id = "194751";
+        let input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        for k in [0.03, -0.03] {
+            let engine = Engine::new(read_json(&format!("dev/test_data/cards/{test_id}/scan.json")));
+            let (output, _) = engine.recognize(&warp_keystone(&input_images, k));
+            assert!(output.images.iter().all(|status| status.code == 0));
+            assert_eq!(output.exam_number.and_then(|exam_number| exam_number.value), Some("21112".to_string()));
+        }
+    }
+
+
+    #[test]
+    fn test_keystone_limit() {
+        // 支持范围的上限：上下边长相差10%
+        let test_id = "194751";
+        let input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        for k in [0.05, -0.05] {
+            let engine = Engine::new(read_json(&format!("dev/test_data/cards/{test_id}/scan.json")));
+            let (output, _) = engine.recognize(&warp_keystone(&input_images, k));
+            assert!(output.images.iter().all(|status| status.code == 0));
+            assert!(output.pages.iter().all(|page| page.location_marks.len() == 9));
+            assert_eq!(output.exam_number.and_then(|exam_number| exam_number.value), Some("21112".to_string()));
+        }
+    }
+
+
+    #[test]
+    fn test_fold() {
+        // 左右两部分沿竖直折痕各偏转0.02弧度，折痕在第一列和第二列定位点之间
+        use imageproc::geometric_transformations::{warp, Interpolation, Projection};
+        let test_id = "194751";
+        let input_images = read_image(&format!("dev/test_data/cards/{test_id}/images")).expect("Read Image Failed");
+        let images = input_images.images.iter()
+            .map(|base64_image| {
+                let img = trans_base64_to_image(base64_image);
+                let img = if img.width() < img.height() { img.rotate90() } else { img }.to_rgb8();
+                let h = img.height() as f32;
+                let crease = img.width() * 3 / 10;
+                let rotate = |theta: f32| {
+                    let projection = Projection::translate(crease as f32, h / 2.0) * Projection::rotate(theta) * Projection::translate(-(crease as f32), -h / 2.0);
+                    warp(&img, &projection, Interpolation::Bilinear, image::Rgb([255, 255, 255]))
+                };
+                let (mut folded, right) = (rotate(0.02), rotate(-0.02));
+                for (x, y, pixel) in right.enumerate_pixels() {
+                    if x >= crease {
+                        folded.put_pixel(x, y, *pixel);
+                    }
+                }
+                my_utils::image::image_to_base64(&folded)
+            })
+            .collect();
+        let folded_images = InputImage{task_id: input_images.task_id.clone(), images, calling_type: input_images.calling_type};
+        let engine = Engine::new(read_json(&format!("dev/test_data/cards/{test_id}/scan.json")));
+        let (output, _) = engine.recognize(&folded_images);
+        assert!(output.pages.iter().all(|page| page.fold_compensated));
+        assert_eq!(output.exam_number.and_then(|exam_number| exam_number.value), Some("21112".to_string()));
+
+        // 梯形畸变下同一行、同一列的定位点仍然共线，不算折过
+        let engine = Engine::new(read_json(&format!("dev/test_data/cards/{test_id}/scan.json")));
+        let (output, _) = engine.recognize(&warp_keystone(&input_images, 0.05));
+        assert!(output.pages.iter().all(|page| page.has_page && !page.fold_compensated));
+    }
+
+
+    /// 在白底灰度图上画粗笔画，每个笔画是一条折线
+    pub(crate) fn draw_strokes(w: u32, h: u32, strokes: &[&[(f32, f32)]]) -> image::DynamicImage {
+        let mut img = image::GrayImage::from_pixel(w, h, image::Luma([255]));
+        for stroke in strokes {
+            for pair in stroke.windows(2) {
+                let steps = (pair[1].0 - pair[0].0).abs().max((pair[1].1 - pair[0].1).abs()).max(1.0) as i32;
+                for i in 0..=steps {
+                    let t = i as f32 / steps as f32;
+                    let x = pair[0].0 + (pair[1].0 - pair[0].0) * t;
+                    let y = pair[0].1 + (pair[1].1 - pair[0].1) * t;
+                    imageproc::drawing::draw_filled_circle_mut(&mut img, (x as i32, y as i32), 2, image::Luma([0]));
+                }
+            }
+        }
+        image::DynamicImage::ImageLuma8(img)
+    }
+
+    pub(crate) fn read_json(json_path: &str) -> InputScan {
         
         let scan_path = Path::new(json_path).to_str().expect("Parse Json Path Failed").to_string();
         let mut file = File::open(scan_path).expect("Failed to open file");
@@ -109,10 +465,15 @@ mod tests {
 
 use models::scan_json::{InputImage, InputScan};
 use recognition::engine::Engine;
+use recognition::registry::TemplateRegistry;
 use wasm_bindgen::prelude::*;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
 
 // 全局变量的引擎结构体
 static mut ENGINE: Option<Engine> = None;
+// 多模板识别的模板注册表
+static REGISTRY: Lazy<Mutex<TemplateRegistry>> = Lazy::new(|| Mutex::new(TemplateRegistry::new()));
 
 #[wasm_bindgen]
 pub fn initialize(input_json: &str){
@@ -138,4 +499,24 @@ pub fn inference(input_json:&str) -> String {
         // 使用 serde_json 将结果序列化为 JSON 字符串
         serde_json::to_string(&output_json).expect("Failed to serialize JSON")
     }
-}
\ No newline at end of file
+}
+
+/// 预加载模板，template_id为答题卡上二维码或条形码的内容
+#[wasm_bindgen]
+pub fn register_template(template_id: &str, input_json: &str){
+    let input_scan: InputScan = serde_json::from_str(input_json).expect("Parse Input Failed");
+    let input_scan = input_scan.renew();
+    REGISTRY.lock().expect("Registry lock poisoned").register(template_id, input_scan);
+}
+
+/// 不绑定scanjson，按整页上的码从注册表中选择模板识别
+#[wasm_bindgen]
+pub fn inference_by_code(input_json:&str) -> String {
+    let registry = REGISTRY.lock().expect("Registry lock poisoned");
+    assert!(!registry.is_empty(), "No template registered");
+
+    let input_image: InputImage = serde_json::from_str(input_json).expect("Parse Input Failed");
+    let output_json = registry.recognize(&input_image);
+
+    serde_json::to_string(&output_json).expect("Failed to serialize JSON")
+}
@@ -31,7 +31,15 @@ pub mod engine_rec{
     /// 标注定位点和实际定位点，用来参照计算其他标注框的真实坐标
     pub struct ReferenceModelPoints<'a>{
         pub model_points: &'a [ModelPoint;4],
-        pub real_model_points: &'a [Coordinate;4]
+        pub real_model_points: &'a [Coordinate;4],
+        /// 全部标注定位点，按grid_columns列逐行排列
+        pub model_points_all: &'a [ModelPoint],
+        /// 全部定位点在图中的位置，检测不到的为None，为空时只用四个角的定位点
+        pub real_model_points_all: &'a [Option<Coordinate>],
+        /// 定位点网格的列数，即card_columns+1
+        pub grid_columns: usize,
+        /// 纸张被折过，网格定位点不全时每列按本列上下两行的定位点分别对齐
+        pub folded: bool
     }
 
     /// 识别需要用到的各种图片
@@ -51,10 +59,35 @@ pub mod engine_rec{
         pub integral_morphology: ImageBuffer<Luma<i64>, Vec<i64>>
     }
 
+    /// 整页填涂选项的统计，用来判断单个选项是空白、填涂、擦除还是存疑
+    pub struct FillStatistics{
+        /// 空白选项的平均填涂率
+        pub blank: f32,
+        /// 填涂选项的平均填涂率
+        pub filled: f32,
+        /// 本页空白和填涂的分界
+        pub threshold: f32
+    }
+
+    /// 定位点和模板布局的拟合结果
+    #[derive(Clone)]
+    pub struct MarkFit{
+        /// 和模板定位点对应上的定位点
+        pub marks: Vec<Coordinate>,
+        /// 定位点中心到拟合位置的均方根距离，单位为像素
+        pub residual: f32
+    }
+
     #[derive(Clone)]
     pub struct ProcessedImagesAndModelPoints{
         pub img: ProcessedImages,
-        pub real_model_points: [Coordinate;4]
+        pub real_model_points: [Coordinate;4],
+        /// 匹配到page后检测的全部定位点，和page.model_points一一对应
+        pub real_model_points_all: Vec<Option<Coordinate>>,
+        /// 根据全部定位点判断纸张被折过
+        pub folded: bool,
+        /// 定位点拟合结果，拟合失败时为None
+        pub mark_fit: Option<MarkFit>
     }
 }
 
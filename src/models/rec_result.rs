@@ -4,7 +4,7 @@
 
 use serde::{Serialize, Deserialize};
 
-use super::scan_json::{InputScan, Coordinate};
+use super::scan_json::{self, InputScan, Coordinate};
 
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,17 +13,85 @@ pub struct OutputRec{
     pub code: u8,
     pub message: String,
     pub pages: Vec<Page>,
-    pub images: Vec<ImageStatus>
+    pub images: Vec<ImageStatus>,
+    /// 存在存疑选项、需要人工复核的题目
+    pub ambiguous_rec_ids: Vec<String>,
+    pub exam_number: Option<ExamNumber>,
+    /// 缺考标记被填涂
+    pub absent: bool
 }
 
 
+/// 多模板识别的输出
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutputMultiTemplate{
+    pub task_id: String,
+    /// 每份答题卡一份识别结果，同一模板可以有多份
+    pub results: Vec<TemplateOutputRec>,
+    /// 找不到模板的图片序号
+    pub unmatched_images: Vec<usize>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TemplateOutputRec{
+    pub template_id: String,
+    /// 属于该答题卡的图片在输入中的序号
+    pub image_indices: Vec<usize>,
+    pub output: OutputRec
+}
+
+
+/// 考号识别结果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExamNumber{
+    /// 完整考号，有任意一列异常时为None
+    pub value: Option<String>,
+    pub columns: Vec<ExamNumberColumn>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExamNumberColumn{
+    pub value: Option<String>,
+    pub code: u8,
+    pub message: String
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImageStatus{
     pub image_source: String,
+    /// 在输入images中的序号
+    pub input_index: usize,
+    /// CONFIG.image_status_code中的状态码
     pub code: u8,
+    pub message: String,
+    /// 匹配到的page序号
+    pub page_index: Option<usize>,
+    /// 匹配时图片顺时针旋转的角度，0、90、180或270
+    pub orientation: Option<i32>,
+    /// 匹配时图片在旋转后水平翻转，用于纸张反面扫描
+    pub mirrored: Option<bool>,
+    /// 原图被拆成多页时是第几份
+    pub part: Option<usize>,
+    /// 重复图片对应的已匹配图片在输入中的序号
+    pub duplicate_of: Option<usize>,
+    /// 去掉四周后的平均灰度，用来判断空白页
+    pub mean_intensity: f32,
+    /// 和每个page、每个方向的页码差异，用于核查匹配
+    pub page_diffs: Vec<PageDiff>,
     pub page_size: PageSize
 }
 
+/// 图片按某个方向和某个page的页码差异
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageDiff{
+    pub page_index: usize,
+    /// 图片顺时针旋转的角度，0、90、180或270
+    pub orientation: i32,
+    /// 旋转后是否水平翻转
+    pub mirrored: bool,
+    pub diff: f32
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Page{
     pub has_page: bool,
@@ -31,13 +99,38 @@ pub struct Page{
     pub image_source: Option<String>,
     pub image_rotated: Option<String>,
     pub image_rendering: Option<String>,
-    pub recognizes: Vec<Recognize>
+    pub recognizes: Vec<Recognize>,
+    /// 纸张被折过，已按网格或按列分别对齐
+    pub fold_compensated: bool,
+    /// 检测到的定位点
+    pub location_marks: Vec<Coordinate>,
+    /// 定位点和模板布局的拟合残差，单位为像素，拟合失败时为None
+    pub location_mark_residual: Option<f32>,
+    /// 本页图片在原始输入中的来源
+    pub source: Option<ImageSource>
+}
+
+/// 拆分后图片在原始输入中的来源
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageSource{
+    /// 原始输入图片的序号
+    pub input_index: usize,
+    /// 原图拆成的份数，没有拆分为1
+    pub parts: usize,
+    /// 第几份，按原图从左到右或从上到下
+    pub part: usize
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Recognize{
     pub rec_id: String,
     pub rec_type: u8,
+    /// 整题的识别结果，如选择题的"AC"
+    pub rec_value: Option<Value>,
+    /// 有存疑的选项，整题不输出结果
+    pub ambiguous: bool,
+    /// 整题置信度，取各选项中最低的
+    pub confidence: Option<f32>,
     pub rec_options: Vec<RecOption>
 }
 
@@ -45,7 +138,15 @@ pub struct Recognize{
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RecOption{
     pub value: Option<Value>,
-    pub coordinate: Option<Coordinate>
+    pub coordinate: Option<Coordinate>,
+    /// 填涂状态，见CONFIG.fill_status
+    pub fill_status: Option<u8>,
+    /// 识别置信度，0-1
+    pub confidence: Option<f32>,
+    /// 填涂题的原始填涂率
+    pub fill_rate: Option<f32>,
+    /// 单个条形码的码制、位置和识别步骤，value中只有内容
+    pub barcode: Option<BarcodeValue>
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,6 +161,45 @@ pub enum Value {
     String(String),
     Integer(i32),
     Float(f32),
+    Barcode(BarcodeValue),
+    /// 一个选项有多个结果，如同一个框里的多个条码
+    List(Vec<Value>),
+}
+
+/// 二维码识别结果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BarcodeValue{
+    pub text: String,
+    /// 码制，如QR_CODE
+    pub format: String,
+    /// 码在图中的旋转角度
+    pub orientation: Option<i32>,
+    /// 码在页面中的位置
+    pub coordinate: Option<Coordinate>,
+    /// 识别成功的那次尝试，如original、upscale
+    pub attempt: String
+}
+
+impl From<&scan_json::Value> for Value {
+    fn from(value: &scan_json::Value) -> Self {
+        match value {
+            scan_json::Value::String(v) => Value::String(v.clone()),
+            scan_json::Value::Integer(v) => Value::Integer(*v),
+            scan_json::Value::Float(v) => Value::Float(*v),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::String(v) => write!(f, "{v}"),
+            Value::Integer(v) => write!(f, "{v}"),
+            Value::Float(v) => write!(f, "{v}"),
+            Value::Barcode(v) => write!(f, "{}", v.text),
+            Value::List(v) => write!(f, "{}", v.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(",")),
+        }
+    }
 }
 
 impl OutputRec{
@@ -75,14 +215,25 @@ impl OutputRec{
                     image_source: None,
                     image_rendering: None,
                     image_rotated: None,
+                    fold_compensated: false,
+                    location_marks: Vec::new(),
+                    location_mark_residual: None,
+                    source: None,
                     recognizes: page.recognizes.iter().map(|rec| {
                         Recognize {
                             rec_id: rec.rec_id.clone(),
                             rec_type: rec.rec_type,
+                            rec_value: None,
+                            ambiguous: false,
+                            confidence: None,
                             rec_options: rec.options.iter().map(|_|{
                                 RecOption{
                                     value: None,
-                                    coordinate: None
+                                    coordinate: None,
+                                    fill_status: None,
+                                    confidence: None,
+                                    fill_rate: None,
+                                    barcode: None
                                 }
                             }).collect()
                         }
@@ -90,6 +241,9 @@ impl OutputRec{
                 }
             }).collect(),
             images: Vec::new(),
+            ambiguous_rec_ids: Vec::new(),
+            exam_number: None,
+            absent: false,
         }
     }
 }
\ No newline at end of file
@@ -26,6 +26,11 @@ pub struct Page {
     pub page_number_points: Vec<PageNumberPoint>,
     pub recognizes: Vec<Recognition>,
     pub model_points_4: Option<[ModelPoint;4]>,
+    /// 考号填涂区，每列一个Recognition，选项值为"0"-"9"
+    #[serde(rename = "examNumber")]
+    pub exam_number: Option<Vec<Recognition>>,
+    /// 缺考标记框，监考老师填涂表示该考生缺考
+    pub missing: Option<Recognition>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -59,6 +64,8 @@ pub struct Recognition {
     pub rec_id: String,
     pub rec_type: u8,
     pub options: Vec<Item>,
+    /// 选择题类型，单选、多选、判断，见CONFIG.choice_type
+    pub options_type: Option<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -123,6 +130,8 @@ impl Page {
             page_number_points:self.page_number_points.clone(),
             recognizes:self.recognizes.clone(),
             model_points_4:Some([lt, rt, ld, rd]),
+            exam_number:self.exam_number.clone(),
+            missing:self.missing.clone(),
         }
     }
 }
\ No newline at end of file
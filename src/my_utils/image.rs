@@ -1,6 +1,7 @@
 use std::io::Cursor;
 
-use image::{DynamicImage, ImageBuffer, Luma, Rgb, RgbImage, ImageFormat};
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Rgb, RgbImage, ImageFormat};
+use image::imageops::{crop_imm, flip_horizontal_in_place, rotate180_in_place, rotate90};
 use imageproc::distance_transform::Norm;
 use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
 use imageproc::morphology::{dilate, erode};
@@ -8,7 +9,6 @@ use imageproc::{filter::gaussian_blur_f32, point::Point};
 use imageproc::integral_image::{integral_image, sum_image_pixels};
 
 use crate::models::engine_rec::{ProcessedImages, ReferenceModelPoints};
-use crate::models::scan_json::ModelSize;
 use crate::{config::CONFIG, models::{card::MyPoint, scan_json::Coordinate}};
 use super::math::*;
 use anyhow::{Result,Ok};
@@ -171,7 +171,24 @@ pub fn rotate_point(point: &MyPoint, center: &MyPoint, angle_rad: f32) -> (i32,
 }
 
 /// 参照定位点得到标注coodinate对应的真实coordinate
+/// 优先用框所在网格的四个定位点做透视变换，所在网格的定位点没有全部检测到时用四个角的定位点
+/// 透视变换可以校正手机拍照的梯形畸变，四点退化时按水平竖直等比缩放
+/// 支持的梯形畸变以定位点能拟合为限，对边长度相差不超过10%
 pub fn generate_real_coordinate_with_model_points(reference_model_points: &ReferenceModelPoints, coordinate: &Coordinate) -> Coordinate{
+    if let Some(real_coordinate) = generate_real_coordinate_with_local_quad(reference_model_points, coordinate) {
+        return real_coordinate;
+    }
+    if reference_model_points.folded {
+        if let Some(real_coordinate) = generate_real_coordinate_with_column(reference_model_points, coordinate) {
+            return real_coordinate;
+        }
+    }
+    let model_corners = reference_model_points.model_points.map(|point| (point.coordinate.x as f32, point.coordinate.y as f32));
+    let real_corners = reference_model_points.real_model_points.map(|point| (point.x as f32, point.y as f32));
+    if let Some(real_coordinate) = solve_homography(&model_corners, &real_corners)
+        .and_then(|homography| map_coordinate_with_homography(&homography, coordinate)) {
+        return real_coordinate;
+    }
     let model_points = &reference_model_points.model_points;
     let real_model_points = &reference_model_points.real_model_points;
     let x_rate = ((real_model_points[0].x - real_model_points[1].x) as f32) / ((model_points[0].coordinate.x - model_points[1].coordinate.x) as f32);
@@ -195,6 +212,110 @@ pub fn generate_real_coordinate_with_model_points(reference_model_points: &Refer
     
 }
 
+/// 用框中心所在网格的四个定位点做透视变换
+/// 纸张被扫描仪拉伸或弯曲时，各列按自己的定位点对齐
+/// 网格外的框用最近的边缘网格外推
+fn generate_real_coordinate_with_local_quad(reference_model_points: &ReferenceModelPoints, coordinate: &Coordinate) -> Option<Coordinate> {
+    let columns = reference_model_points.grid_columns;
+    let model_points = reference_model_points.model_points_all;
+    let real_model_points = reference_model_points.real_model_points_all;
+    if columns < 2 || model_points.len() != real_model_points.len() || model_points.len() % columns != 0 {
+        return None;
+    }
+    let rows = model_points.len() / columns;
+    if rows < 2 {
+        return None;
+    }
+
+    // 框中心所在的网格
+    let center_x = coordinate.x + coordinate.w / 2;
+    let center_y = coordinate.y + coordinate.h / 2;
+    let column = (0..columns - 1)
+        .find(|&i| center_x < model_points[i + 1].coordinate.x)
+        .unwrap_or(columns - 2);
+    let row = (0..rows - 1)
+        .find(|&j| center_y < model_points[(j + 1) * columns].coordinate.y)
+        .unwrap_or(rows - 2);
+
+    // 网格四角：左上、右上、左下、右下
+    let indexes = [
+        row * columns + column,
+        row * columns + column + 1,
+        (row + 1) * columns + column,
+        (row + 1) * columns + column + 1,
+    ];
+    let mut real_corners = [(0.0f32, 0.0f32); 4];
+    for (corner, &index) in real_corners.iter_mut().zip(indexes.iter()) {
+        let point = real_model_points[index]?;
+        *corner = (point.x as f32, point.y as f32);
+    }
+    let model_corners = indexes.map(|index| {
+        let point = model_points[index].coordinate;
+        (point.x as f32, point.y as f32)
+    });
+    let homography = solve_homography(&model_corners, &real_corners)?;
+    map_coordinate_with_homography(&homography, coordinate)
+}
+
+/// 纸张被折过时，每个框是否都能按所在网格或所在列的定位点分别对齐
+/// 全部定位点都检测到时每个网格单独对齐；否则至少三列、第一行和最后一行定位点齐全时按列对齐
+pub fn can_compensate_fold(reference_model_points: &ReferenceModelPoints) -> bool {
+    let columns = reference_model_points.grid_columns;
+    let real_model_points = reference_model_points.real_model_points_all;
+    if columns < 2 || real_model_points.len() != reference_model_points.model_points_all.len() || real_model_points.len() < columns * 2 {
+        return false;
+    }
+    if real_model_points.iter().all(Option::is_some) {
+        return true;
+    }
+    let last_row_start = real_model_points.len() - columns;
+    columns >= 3 && real_model_points[..columns].iter().chain(real_model_points[last_row_start..].iter()).all(Option::is_some)
+}
+
+/// 纸张被折过时，用框所在列第一行和最后一行的四个定位点做透视变换
+/// 中间行的定位点没检测到时，折痕两侧仍然分开对齐
+fn generate_real_coordinate_with_column(reference_model_points: &ReferenceModelPoints, coordinate: &Coordinate) -> Option<Coordinate> {
+    let columns = reference_model_points.grid_columns;
+    let model_points = reference_model_points.model_points_all;
+    let real_model_points = reference_model_points.real_model_points_all;
+    // 只有左右两列时按列对齐就是按四个角对齐
+    if columns < 3 || model_points.len() != real_model_points.len() || model_points.len() < columns * 2 {
+        return None;
+    }
+    let last_row_start = model_points.len() - columns;
+    let center_x = coordinate.x + coordinate.w / 2;
+    let column = (0..columns - 1)
+        .find(|&i| center_x < model_points[i + 1].coordinate.x)
+        .unwrap_or(columns - 2);
+
+    let indexes = [column, column + 1, last_row_start + column, last_row_start + column + 1];
+    let mut real_corners = [(0.0f32, 0.0f32); 4];
+    for (corner, &index) in real_corners.iter_mut().zip(indexes.iter()) {
+        let point = real_model_points[index]?;
+        *corner = (point.x as f32, point.y as f32);
+    }
+    let model_corners = indexes.map(|index| {
+        let point = model_points[index].coordinate;
+        (point.x as f32, point.y as f32)
+    });
+    let homography = solve_homography(&model_corners, &real_corners)?;
+    map_coordinate_with_homography(&homography, coordinate)
+}
+
+/// 变换框的左上和右下两个角得到真实框
+fn map_coordinate_with_homography(homography: &[f32; 9], coordinate: &Coordinate) -> Option<Coordinate> {
+    let (x0, y0) = apply_homography(homography, (coordinate.x as f32, coordinate.y as f32))?;
+    let (x1, y1) = apply_homography(
+        homography, ((coordinate.x + coordinate.w) as f32, (coordinate.y + coordinate.h) as f32)
+    )?;
+    Some(Coordinate{
+        x: x0 as i32,
+        y: y0 as i32,
+        w: (x1 - x0) as i32,
+        h: (y1 - y0) as i32
+    })
+}
+
 pub fn trans_base64_to_image(base64_image: &String) -> DynamicImage {
     let base64_data = from_base64(base64_image.clone());
     // 将解码后的数据加载为图像
@@ -204,15 +325,13 @@ pub fn trans_base64_to_image(base64_image: &String) -> DynamicImage {
 }
 
 /// 处理图片，返回图片预处理过程每一步中间图
-/// 并根据长宽比例完成图片的90度翻转
-pub fn process_image(model_size: &ModelSize, base64_image: &String) -> ProcessedImages {
-    let mut img = trans_base64_to_image(base64_image);
-    // 如果标注的长宽大小和图片的长宽大小关系不同，说明图片需要90度偏转
-    let flag_need_90 = (model_size.h > model_size.w) != (img.height() > img.width());
-    if flag_need_90{
-        img = img.rotate90();
-    };
-    
+/// 不做方向处理，方向在匹配page时确定
+pub fn process_image(base64_image: &String) -> ProcessedImages {
+    process_dynamic_image(&trans_base64_to_image(base64_image), Some(base64_image.clone()))
+}
+
+/// 处理已经解码的图片，org为原始图片的base64
+pub fn process_dynamic_image(img: &DynamicImage, org: Option<String>) -> ProcessedImages {
     let rgb_img = img.to_rgb8();
     let gray_img = img.to_luma8();
     // 对灰度图像进行高斯模糊
@@ -234,7 +353,7 @@ pub fn process_image(model_size: &ModelSize, base64_image: &String) -> Processed
     let integral_morphology:ImageBuffer<Luma<i64>, Vec<i64>> = integral_image(&eroded_img);
 
     ProcessedImages{
-        org: Some(base64_image.clone()),
+        org,
         rgb: rgb_img,
         gray: gray_img,
         morphology: eroded_img,
@@ -252,6 +371,59 @@ pub fn rotate_processed_image(img: &mut ProcessedImages, angle_radians: f32){
     img.integral_morphology = integral_image(&img.morphology);
 }
 
+/// 顺时针旋转90度，输出新的ProcessedImages
+pub fn rotate_processed_image_clockwise_90(img: &ProcessedImages) -> ProcessedImages {
+    let gray = rotate90(&img.gray);
+    let morphology = rotate90(&img.morphology);
+    ProcessedImages{
+        org: img.org.clone(),
+        rgb: rotate90(&img.rgb),
+        integral_gray: integral_image(&gray),
+        integral_morphology: integral_image(&morphology),
+        gray,
+        morphology,
+    }
+}
+
+/// 旋转180度和水平翻转，像素一一对应不插值
+pub fn flip_processed_image(img: &mut ProcessedImages, rotate_180: bool, mirror: bool){
+    if rotate_180 {
+        rotate180_in_place(&mut img.rgb);
+        rotate180_in_place(&mut img.gray);
+        rotate180_in_place(&mut img.morphology);
+    }
+    if mirror {
+        flip_horizontal_in_place(&mut img.rgb);
+        flip_horizontal_in_place(&mut img.gray);
+        flip_horizontal_in_place(&mut img.morphology);
+    }
+    img.integral_gray = integral_image(&img.gray);
+    img.integral_morphology = integral_image(&img.morphology);
+}
+
+/// 计算框内的填涂率，0为全白，1为全黑
+/// 坐标超出图片的部分会被裁掉，完全在图片外的框填涂率为0
+pub fn calculate_fill_rate(
+    integral_img: &ImageBuffer<Luma<i64>, Vec<i64>>,
+    coordinate: &Coordinate
+) -> f32 {
+    // 积分图比原图宽高各多1
+    let max_x = integral_img.width() as i32 - 2;
+    let max_y = integral_img.height() as i32 - 2;
+    let left = coordinate.x.clamp(0, max_x);
+    let top = coordinate.y.clamp(0, max_y);
+    let right = (coordinate.x + coordinate.w - 1).clamp(0, max_x);
+    let bottom = (coordinate.y + coordinate.h - 1).clamp(0, max_y);
+    if right < left || bottom < top {
+        return 0.0;
+    }
+    let sum_pixel = sum_image_pixels(
+        integral_img, left as u32, top as u32, right as u32, bottom as u32
+    )[0];
+    let area = ((right - left + 1) * (bottom - top + 1)) as i64;
+    1.0 - sum_pixel as f32 / area as f32 / 255f32
+}
+
 /// 计算页码点标注填涂率和真实填涂率的距离
 pub fn calculate_page_number_difference(
     integral_img: &ImageBuffer<Luma<i64>, Vec<i64>>,
@@ -260,16 +432,8 @@ pub fn calculate_page_number_difference(
 ) -> f32 {
     let mut real_fill_rates: Vec<f32> = Vec::new();
     for coordinate in coordinates.iter(){
-        let sum_pixel = sum_image_pixels(
-            integral_img,
-            coordinate.x as u32,
-            coordinate.y as u32,
-            coordinate.x as u32 + coordinate.w as u32,
-            coordinate.y as u32 + coordinate.h as u32
-        )[0];
-        let mean_pixel = sum_pixel / (coordinate.w * coordinate.h) as i64;
-        let rate_pixel = 1.0 - mean_pixel as f32 / 255f32;
-        real_fill_rates.push(rate_pixel);
+        // 超出图片的部分裁掉，避免积分图越界
+        real_fill_rates.push(calculate_fill_rate(integral_img, coordinate));
     }
 
     mean_absolute_difference(&fill_rates, &real_fill_rates)
@@ -283,9 +447,26 @@ pub fn image_to_base64(img: &RgbImage) -> String {
 
 
 
-/**
- * 截取图像
- */
-pub fn crop_image(image: &DynamicImage, coor: Coordinate) -> DynamicImage {
-    image.crop_imm(coor.x as u32, coor.y as u32, coor.w as u32, coor.h as u32)
+/// 裁掉坐标超出图片的部分，完全在图片外时输出None
+pub fn clip_coordinate(coor: &Coordinate, width: u32, height: u32) -> Option<Coordinate> {
+    let left = coor.x.max(0);
+    let top = coor.y.max(0);
+    let right = (coor.x + coor.w).min(width as i32);
+    let bottom = (coor.y + coor.h).min(height as i32);
+    if right <= left || bottom <= top {
+        return None;
+    }
+    Some(Coordinate{x: left, y: top, w: right - left, h: bottom - top})
+}
+
+/// 截取图像，坐标超出图片的部分会被裁掉，完全在图片外时输出None
+pub fn crop_image(image: &DynamicImage, coor: &Coordinate) -> Option<DynamicImage> {
+    let coor = clip_coordinate(coor, image.width(), image.height())?;
+    Some(image.crop_imm(coor.x as u32, coor.y as u32, coor.w as u32, coor.h as u32))
+}
+
+/// 截取灰度图，坐标超出图片的部分会被裁掉，完全在图片外时输出None
+pub fn crop_gray_image(image: &GrayImage, coor: &Coordinate) -> Option<GrayImage> {
+    let coor = clip_coordinate(coor, image.width(), image.height())?;
+    Some(crop_imm(image, coor.x as u32, coor.y as u32, coor.w as u32, coor.h as u32).to_image())
 }
\ No newline at end of file
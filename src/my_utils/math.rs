@@ -22,3 +22,289 @@ pub fn euclidean_distance(point1: (f32, f32), point2: (f32, f32)) -> f32 {
 
     (dx.powi(2) + dy.powi(2)).sqrt()
 }
+
+/// 一维数据分成两类，穷举分割点使类间方差最大（大津法）
+/// 输出(低类均值, 高类均值)，数据不足两个或只有一种取值时输出None
+pub fn two_cluster_split(values: &[f32]) -> Option<(f32, f32)> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len();
+    if n < 2 || sorted[0] == sorted[n - 1] {
+        return None;
+    }
+    let total: f32 = sorted.iter().sum();
+
+    let mut best_variance = -1.0;
+    let mut best = None;
+    let mut low_sum = 0.0;
+    for i in 1..n {
+        low_sum += sorted[i - 1];
+        // 相同取值不能分到两类
+        if sorted[i - 1] == sorted[i] {
+            continue;
+        }
+        let low_n = i as f32;
+        let high_n = (n - i) as f32;
+        let low_mean = low_sum / low_n;
+        let high_mean = (total - low_sum) / high_n;
+        let variance = low_n * high_n * (high_mean - low_mean).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = Some((low_mean, high_mean));
+        }
+    }
+    best
+}
+
+/// 由四对对应点求单应矩阵（透视变换），按行排列的3x3矩阵，右下角为1
+/// 四点中有三点共线等退化情况输出None
+pub fn solve_homography(src: &[(f32, f32); 4], dst: &[(f32, f32); 4]) -> Option<[f32; 9]> {
+    // 每对点两个方程，8个未知数，用f64避免大坐标的精度问题
+    let mut a = [[0.0f64; 9]; 8];
+    for (i, (&(x, y), &(u, v))) in src.iter().zip(dst.iter()).enumerate() {
+        let (x, y, u, v) = (x as f64, y as f64, u as f64, v as f64);
+        a[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+        a[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+    }
+    let solution = solve_linear_system_8(a)?;
+    let mut h = [1.0f32; 9];
+    for (value, solved) in h.iter_mut().zip(solution.iter()) {
+        *value = *solved as f32;
+    }
+    Some(h)
+}
+
+/// 由四对以上对应点最小二乘拟合单应矩阵，格式同solve_homography
+/// 点数不足四个或src中找不到四个无三点共线的点时输出None
+pub fn fit_homography(src: &[(f32, f32)], dst: &[(f32, f32)]) -> Option<[f32; 9]> {
+    if src.len() != dst.len() || src.len() < 4 || !has_general_quad(src) {
+        return None;
+    }
+    // 两组点各自平移到重心、缩放到平均距离为1，法方程的数值才稳定
+    let normalize = |points: &[(f32, f32)]| {
+        let n = points.len() as f64;
+        let cx = points.iter().map(|p| p.0 as f64).sum::<f64>() / n;
+        let cy = points.iter().map(|p| p.1 as f64).sum::<f64>() / n;
+        let mean_distance = points.iter().map(|p| ((p.0 as f64 - cx).powi(2) + (p.1 as f64 - cy).powi(2)).sqrt()).sum::<f64>() / n;
+        let scale = if mean_distance > 0.0 { 1.0 / mean_distance } else { 1.0 };
+        let normalized: Vec<(f64, f64)> = points.iter().map(|p| ((p.0 as f64 - cx) * scale, (p.1 as f64 - cy) * scale)).collect();
+        (normalized, cx, cy, scale)
+    };
+    let (src_n, sx, sy, ss) = normalize(src);
+    let (dst_n, dx, dy, ds) = normalize(dst);
+
+    // 法方程 AᵀA h = Aᵀb
+    let mut a = [[0.0f64; 9]; 8];
+    for (&(x, y), &(u, v)) in src_n.iter().zip(dst_n.iter()) {
+        for row in [[x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u], [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v]] {
+            for i in 0..8 {
+                for j in 0..9 {
+                    a[i][j] += row[i] * row[j];
+                }
+            }
+        }
+    }
+    let solution = solve_linear_system_8(a)?;
+    let hn = [
+        solution[0], solution[1], solution[2],
+        solution[3], solution[4], solution[5],
+        solution[6], solution[7], 1.0,
+    ];
+    // 还原归一化：H = T_dst⁻¹ · Hn · T_src
+    let t_src = [ss, 0.0, -ss * sx, 0.0, ss, -ss * sy, 0.0, 0.0, 1.0];
+    let t_dst_inverse = [1.0 / ds, 0.0, dx, 0.0, 1.0 / ds, dy, 0.0, 0.0, 1.0];
+    let multiply = |m: &[f64; 9], n: &[f64; 9]| {
+        let mut out = [0.0f64; 9];
+        for i in 0..3 {
+            for j in 0..3 {
+                out[i * 3 + j] = (0..3).map(|k| m[i * 3 + k] * n[k * 3 + j]).sum();
+            }
+        }
+        out
+    };
+    let h = multiply(&t_dst_inverse, &multiply(&hn, &t_src));
+    if h[8].abs() < 1e-12 {
+        return None;
+    }
+    Some(h.map(|value| (value / h[8]) as f32))
+}
+
+/// 点集中存在四个点，其中任意三点不共线
+/// 多于三点共线时最小二乘的单应矩阵不唯一，拟合结果不可信
+fn has_general_quad(points: &[(f32, f32)]) -> bool {
+    let (min_x, max_x) = points.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+    let (min_y, max_y) = points.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+    let extent = (max_x - min_x).max(max_y - min_y);
+    // 三角形面积小于范围的千分之一算共线
+    let min_area = extent * extent * 1e-3;
+    let collinear = |a: (f32, f32), b: (f32, f32), c: (f32, f32)| {
+        ((b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)).abs() / 2.0 < min_area
+    };
+    let n = points.len();
+    for i in 0..n {
+        for j in i + 1..n {
+            for k in j + 1..n {
+                if collinear(points[i], points[j], points[k]) {
+                    continue;
+                }
+                for l in k + 1..n {
+                    let (a, b, c, d) = (points[i], points[j], points[k], points[l]);
+                    if !collinear(a, b, d) && !collinear(a, c, d) && !collinear(b, c, d) {
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
+/// 由三对以上对应点最小二乘拟合仿射变换，格式同solve_homography，最后一行为0, 0, 1
+/// 点数不足或全部共线时输出None
+pub fn fit_affine(src: &[(f32, f32)], dst: &[(f32, f32)]) -> Option<[f32; 9]> {
+    if src.len() != dst.len() || src.len() < 3 {
+        return None;
+    }
+    let n = src.len() as f64;
+    let mean = |points: &[(f32, f32)]| {
+        (points.iter().map(|p| p.0 as f64).sum::<f64>() / n, points.iter().map(|p| p.1 as f64).sum::<f64>() / n)
+    };
+    let (mx, my) = mean(src);
+    let (mu, mv) = mean(dst);
+    // 去掉重心后，x和y方向各解一个2元法方程，系数矩阵相同
+    let (mut sxx, mut sxy, mut syy) = (0.0f64, 0.0f64, 0.0f64);
+    let (mut sxu, mut syu, mut sxv, mut syv) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    for (&(x, y), &(u, v)) in src.iter().zip(dst.iter()) {
+        let (x, y, u, v) = (x as f64 - mx, y as f64 - my, u as f64 - mu, v as f64 - mv);
+        sxx += x * x;
+        sxy += x * y;
+        syy += y * y;
+        sxu += x * u;
+        syu += y * u;
+        sxv += x * v;
+        syv += y * v;
+    }
+    let det = sxx * syy - sxy * sxy;
+    if det <= 1e-9 * (sxx + syy) * (sxx + syy) {
+        return None;
+    }
+    let a = (sxu * syy - syu * sxy) / det;
+    let b = (syu * sxx - sxu * sxy) / det;
+    let d = (sxv * syy - syv * sxy) / det;
+    let e = (syv * sxx - sxv * sxy) / det;
+    Some([
+        a as f32, b as f32, (mu - a * mx - b * my) as f32,
+        d as f32, e as f32, (mv - d * mx - e * my) as f32,
+        0.0, 0.0, 1.0,
+    ])
+}
+
+/// 列主元高斯消元解8元线性方程组，每行最后一个数为常数项，奇异时输出None
+fn solve_linear_system_8(mut a: [[f64; 9]; 8]) -> Option<[f64; 8]> {
+    for col in 0..8 {
+        let pivot = (col..8).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        a.swap(col, pivot);
+        for row in 0..8 {
+            if row == col {
+                continue;
+            }
+            let pivot_row = a[col];
+            let factor = a[row][col] / pivot_row[col];
+            for (value, pivot_value) in a[row].iter_mut().zip(pivot_row.iter()).skip(col) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+    let mut solution = [0.0f64; 8];
+    for (i, value) in solution.iter_mut().enumerate() {
+        *value = a[i][8] / a[i][i];
+    }
+    Some(solution)
+}
+
+/// 用单应矩阵变换一个点，点落在无穷远处时输出None
+pub fn apply_homography(h: &[f32; 9], point: (f32, f32)) -> Option<(f32, f32)> {
+    let (x, y) = point;
+    let w = h[6] * x + h[7] * y + h[8];
+    if w.abs() < f32::EPSILON {
+        return None;
+    }
+    Some(((h[0] * x + h[1] * y + h[2]) / w, (h[3] * x + h[4] * y + h[5]) / w))
+}
+
+/// 匈牙利算法求总代价最小的一一分配
+/// cost为行×列的代价矩阵，输出每行分配到的列，行多于列时没分到的行为None
+pub fn hungarian(cost: &[Vec<f32>]) -> Vec<Option<usize>> {
+    let rows = cost.len();
+    let columns = cost.iter().map(|row| row.len()).max().unwrap_or(0);
+    let n = rows.max(columns);
+    if n == 0 {
+        return Vec::new();
+    }
+    // 补成方阵，补的格子代价为0，分到补的格子即不分配
+    let at = |i: usize, j: usize| -> f64 {
+        cost.get(i).and_then(|row| row.get(j)).copied().unwrap_or(0.0) as f64
+    };
+    // 行列势，下标从1开始，0为虚拟的起点
+    let mut u = vec![0.0f64; n + 1];
+    let mut v = vec![0.0f64; n + 1];
+    // assigned[j]为分到第j列的行，0表示没有分配
+    let mut assigned = vec![0usize; n + 1];
+    let mut way = vec![0usize; n + 1];
+    for i in 1..=n {
+        assigned[0] = i;
+        let mut j0 = 0;
+        let mut min_slack = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        // 从第i行出发找增广路
+        loop {
+            used[j0] = true;
+            let i0 = assigned[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+                let slack = at(i0 - 1, j - 1) - u[i0] - v[j];
+                if slack < min_slack[j] {
+                    min_slack[j] = slack;
+                    way[j] = j0;
+                }
+                if min_slack[j] < delta {
+                    delta = min_slack[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[assigned[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if assigned[j0] == 0 {
+                break;
+            }
+        }
+        // 沿增广路翻转分配
+        while j0 != 0 {
+            let j1 = way[j0];
+            assigned[j0] = assigned[j1];
+            j0 = j1;
+        }
+    }
+
+    let mut result = vec![None; rows];
+    for (j, &i) in assigned.iter().enumerate().skip(1) {
+        if i >= 1 && i <= rows && j - 1 < cost[i - 1].len() {
+            result[i - 1] = Some(j - 1);
+        }
+    }
+    result
+}
@@ -7,132 +7,669 @@ use image::ImageBuffer;
 use image::Luma;
 use imageproc::contours::find_contours;
 use imageproc::contours::Contour;
-use imageproc::integral_image::sum_image_pixels;
+use imageproc::geometry::{contour_area, convex_hull};
 
 use crate::models::engine_rec::ProcessedImages;
 use crate::models::engine_rec::ReferenceModelPoints;
-use crate::models::engine_rec::{ProcessedImagesAndModelPoints, RecInfoBaizheng};
-use crate::models::rec_result::ImageStatus;
+use crate::models::engine_rec::{MarkFit, ProcessedImagesAndModelPoints, RecInfoBaizheng};
+use crate::models::rec_result::{ImageStatus, PageDiff};
 use crate::models::rec_result::OutputRec;
 use crate::models::rec_result::PageSize;
-use crate::models::scan_json::InputImage;
+use crate::models::scan_json::Page;
 use crate::models::scan_json::PageNumberPoint;
 use crate::models::scan_json::{Coordinate, ModelSize};
 use crate::my_utils::image::*;
 use crate::models::card::MyPoint;
-use crate::my_utils::math::{cosine_similarity, euclidean_distance};
+use crate::my_utils::math::{apply_homography, cosine_similarity, euclidean_distance, fit_affine, fit_homography, hungarian};
 use crate::config::CONFIG;
 use crate::my_utils::node::print2node;
 
 use super::engine::Engine;
 
 pub trait Baizheng{
-    fn baizheng_and_match_page(&self, input_images: &InputImage, output: &mut OutputRec) -> Vec<Option<ProcessedImagesAndModelPoints>>;
+    fn baizheng_and_match_page(&self, input_images: Vec<(ProcessedImages, Vec<Location>)>, output: &mut OutputRec) -> Vec<Option<ProcessedImagesAndModelPoints>>;
 }
 
 
 impl Baizheng for Engine {
+    /// 输入已经处理成ProcessedImages的图片，包含各种预处理的图片，以及拆分时算出的每个方向的定位结果
     /// 输出对应page位置的图片并摆正，未匹配的使用None
-    fn baizheng_and_match_page(&self, input_images: &InputImage, output: &mut OutputRec) -> Vec<Option<ProcessedImagesAndModelPoints>>{
-        // 读图+处理成ProcessedImages，包含各种预处理的图片
+    fn baizheng_and_match_page(&self, input_images: Vec<(ProcessedImages, Vec<Location>)>, output: &mut OutputRec) -> Vec<Option<ProcessedImagesAndModelPoints>>{
+        // 空白图片不参与后续处理，记录输入序号
+        let inputs_len = input_images.len();
         let mut imgs: Vec<ProcessedImages> = Vec::new();
-        for base64_image in &input_images.images{
-            let img = process_image(&self.get_scan_data().pages[0].model_size, base64_image);
-            let mean_pixel = sum_image_pixels(
-                &img.integral_gray, 0, 0, img.morphology.width()-1, img.morphology.height()-1
-            )[0]/((img.morphology.width() * img.morphology.height()) as i64);
-            if mean_pixel > 253{continue;}
+        let mut imgs_locations: Vec<Vec<Location>> = Vec::new();
+        let mut input_indexes: Vec<usize> = Vec::new();
+        let mut blank_images: Vec<usize> = Vec::new();
+        let mut mean_intensities: Vec<f32> = Vec::new();
+        let mut image_sources: Vec<String> = Vec::new();
+        let mut page_sizes: Vec<(u32, u32)> = Vec::new();
+        for (input_index, (img, locations)) in input_images.into_iter().enumerate(){
+            image_sources.push(img.org.clone().expect("org is None"));
+            page_sizes.push(img.rgb.dimensions());
+            let mean_intensity = calculate_mean_intensity(&img);
+            mean_intensities.push(mean_intensity);
+            if mean_intensity > CONFIG.blank_page.min_mean_intensity{
+                blank_images.push(input_index);
+                continue;
+            }
             imgs.push(img);
+            imgs_locations.push(locations);
+            input_indexes.push(input_index);
         }
         let imgs_len = imgs.len();
-        // 获取定位点wh，用于筛选定位点
-        // todo: 后期可以抽象一下，目前只想到这一个
-        let location_wh = (
-            self.get_scan_data().pages[0].model_points[0].coordinate.w,
-            self.get_scan_data().pages[0].model_points[0].coordinate.h,
-        );
-        // 计算每张图片真实定位点
-        // 并根据定位点进行小角度摆正
-        // 将img和定位点组成后续公用的图结构ProcessedImagesAndModelPoints
+        // 长宽比不能确定是否需要90度旋转时两种都试，每种的真实定位点在拆分时已经算出
+        // 根据定位点进行小角度摆正
+        // 将img和定位点组成后续公用的图结构ProcessedImagesAndModelPoints，同时保留在imgs中的序号和旋转角度
+        // 所有方向都定位失败的图片不参与匹配，记录错误信息
         let mut imgs_and_model_points = Vec::new();
-        for mut img in imgs.iter_mut(){
-            let coordinates = generate_location_and_rotate(img, location_wh);
-            imgs_and_model_points.push(
-                ProcessedImagesAndModelPoints{
-                    img: img.clone(),
-                    real_model_points: coordinates,
-                }
-            );
-        }
-        // 下面是判断图片是否需要180旋转
-        // 生成每个图结构的旋转180副本
-        let mut imgs_and_model_points_contains_180 = Vec::new();
-        for img in imgs_and_model_points{
-            let mut img_180 = img.clone();
-            rotate_img_and_model_points_180(&mut img_180);
-            imgs_and_model_points_contains_180.push(img);
-            imgs_and_model_points_contains_180.push(img_180);
+        let mut location_errors: HashMap<usize, String> = HashMap::new();
+        for (index, (img, locations)) in imgs.iter().zip(imgs_locations).enumerate(){
+            let mut location_error = None;
+            let located_before = imgs_and_model_points.len();
+            for Location{quarter_turn, corners} in locations {
+                // 定位点布局按每个page的模板拟合，方向不对时拟合不上
+                let corners = match corners {
+                    Ok(corners) => corners,
+                    Err(message) => {
+                        location_error.get_or_insert(message);
+                        continue;
+                    }
+                };
+                let mut img = if quarter_turn { rotate_processed_image_clockwise_90(img) } else { img.clone() };
+                let (coordinates, mark_fit) = rotate_to_location(&mut img, corners);
+                imgs_and_model_points.push((
+                    index,
+                    if quarter_turn { 90 } else { 0 },
+                    ProcessedImagesAndModelPoints{
+                        img,
+                        real_model_points: coordinates,
+                        real_model_points_all: Vec::new(),
+                        folded: false,
+                        mark_fit,
+                    }
+                ));
+            }
+            if imgs_and_model_points.len() == located_before {
+                location_errors.insert(index, location_error.unwrap_or_default());
+            }
         }
 
-        // 初始化匹配成功的标记
-        let mut is_match_dict: HashMap<usize, bool> = HashMap::new();
-        for i in (0..imgs_len){
-            is_match_dict.insert(i, false);
-        }
+        // 每张图片匹配到的page、旋转角度和是否镜像，没匹配到为None
+        let mut image_matches: Vec<Option<(usize, i32, bool)>> = vec![None; imgs_len];
 
-        // 遍历scan的每个page，从所有图结构里匹配页码差异符合要求的
-        // todo：目前设定了一个差异度阈值，符合后就不做后续匹配了，后期可以加入差异排名做进一步判断
+        // 计算每张图片每个方向和每个page的页码差异
+        // 每个方向再分别尝试旋转180和镜像，镜像用于纸张反面扫描
+        // 每个图片和page取差异小的方向，再整体求总差异最小的一一分配，避免页码相近的两页匹配错
         let scan_size = self.get_scan_data().pages.len();
         let mut processed_images_res: Vec<Option<ProcessedImagesAndModelPoints>> = vec![None;scan_size];
+        let mut page_diffs: Vec<Vec<PageDiff>> = (0..imgs_len).map(|_| Vec::new()).collect();
+        // 差异超过阈值的不能匹配，代价设为远大于任何差异的值，使能匹配的对数优先最多
+        let unmatched_cost = 1000.0;
+        let mut cost = vec![vec![unmatched_cost; scan_size]; imgs_len];
+        // 每个图片和page差异最小的方向在imgs_and_model_points中的位置和翻转方式
+        let mut best_orientation: Vec<Vec<Option<(usize, Flip)>>> = vec![vec![None; scan_size]; imgs_len];
 
         for (index_scan,page) in self.get_scan_data().pages.iter().enumerate(){
-            for (index_image, img_and_model_points) in imgs_and_model_points_contains_180.iter().enumerate(){
-                let match_info = RecInfoBaizheng{
-                    model_size: &page.model_size,
-                    page_number_points: &page.page_number_points,
-                    model_points: page.model_points_4.as_ref().expect("model_points_4 is None")
-                };
-                let flag = match_page_and_img(&match_info, &img_and_model_points);
-                if flag{
-                    let img_and_model_points = img_and_model_points.clone();
-                    processed_images_res[index_scan] = Some(img_and_model_points.clone());
-                    is_match_dict.insert(index_image/2, true);
-                    break
+            let match_info = RecInfoBaizheng{
+                model_size: &page.model_size,
+                page_number_points: &page.page_number_points,
+                model_points: page.model_points_4.as_ref().expect("model_points_4 is None")
+            };
+            for (position, (index_image, orientation, img_and_model_points)) in imgs_and_model_points.iter().enumerate(){
+                for flip in FLIPS {
+                    let diff = match_page_and_img(&match_info, img_and_model_points, flip);
+                    page_diffs[*index_image].push(PageDiff{
+                        page_index: index_scan,
+                        orientation: flip.orientation(*orientation),
+                        mirrored: flip.mirror,
+                        diff
+                    });
+                    if diff <= CONFIG.image_baizheng.page_number_diff && diff < cost[*index_image][index_scan] {
+                        cost[*index_image][index_scan] = diff;
+                        best_orientation[*index_image][index_scan] = Some((position, flip));
+                    }
                 }
             }
         }
-        for (index, flag) in is_match_dict.iter() {
-            let _base64 = imgs[*index].org.as_ref().expect("org is None");
-            let _img = trans_base64_to_image(&_base64);
-            let mut image_status = ImageStatus {
-                image_source: _base64.clone(),
-                code: if *flag { 0 } else { 1 },
+
+        // 同一页上传多次时差异几乎相同，分配结果不确定
+        // 每个page能匹配的图片中，后上传的代价都加上页码差异阈值，差异相近时优先分给先上传的图片
+        for index_scan in 0..scan_size {
+            let Some(first) = (0..imgs_len).find(|&index_image| cost[index_image][index_scan] < unmatched_cost) else {continue};
+            for row in cost.iter_mut().skip(first + 1) {
+                if row[index_scan] < unmatched_cost {
+                    row[index_scan] += CONFIG.image_baizheng.page_number_diff;
+                }
+            }
+        }
+
+        // 每个page分到的图片
+        let mut page_images: Vec<Option<usize>> = vec![None; scan_size];
+        for (index_image, index_scan) in hungarian(&cost).into_iter().enumerate() {
+            let Some(index_scan) = index_scan else {continue};
+            let Some((position, flip)) = best_orientation[index_image][index_scan] else {continue};
+            page_images[index_scan] = Some(index_image);
+            let page = &self.get_scan_data().pages[index_scan];
+            let (_, orientation, img_and_model_points) = &imgs_and_model_points[position];
+            let mut img_and_model_points = img_and_model_points.clone();
+            flip_img_and_model_points(&mut img_and_model_points, flip);
+            // 已经确定page和方向，按四个角预测其余定位点的位置再检测
+            img_and_model_points.real_model_points_all = locate_all_model_points(page, &img_and_model_points);
+            img_and_model_points.folded = detect_fold(page, &img_and_model_points.real_model_points_all);
+            processed_images_res[index_scan] = Some(img_and_model_points);
+            image_matches[index_image] = Some((index_scan, flip.orientation(*orientation), flip.mirror));
+        }
+
+        // 图片状态按输入顺序排列
+        let mut image_statuses: Vec<Option<ImageStatus>> = (0..inputs_len).map(|_| None).collect();
+        for (index, image_match) in image_matches.iter().enumerate() {
+            let input_index = input_indexes[index];
+            // 没分到page但和某个已匹配的page差异足够小，说明是同一页的重复图片，取差异最小的那一页
+            let duplicate_of = if image_match.is_some() {
+                None
+            } else {
+                (0..scan_size)
+                    .filter(|&index_scan| best_orientation[index][index_scan].is_some())
+                    .filter_map(|index_scan| page_images[index_scan].map(|other| (other, cost[index][index_scan])))
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(other, _)| input_indexes[other])
+            };
+            let (code, message) = match location_errors.get(&index) {
+                Some(message) => (CONFIG.image_status_code.location_failed, message.clone()),
+                None if image_match.is_some() => (CONFIG.image_status_code.succeeded, "succeeded".to_string()),
+                None if duplicate_of.is_some() => (CONFIG.image_status_code.duplicate, "page is duplicated".to_string()),
+                None => (CONFIG.image_status_code.unmatched, "no page matched".to_string()),
+            };
+            image_statuses[input_index] = Some(ImageStatus {
+                image_source: image_sources[input_index].clone(),
+                input_index,
+                code,
+                message,
+                page_index: image_match.map(|(index_scan, _, _)| index_scan),
+                orientation: image_match.map(|(_, orientation, _)| orientation),
+                mirrored: image_match.map(|(_, _, mirrored)| mirrored),
+                part: None,
+                duplicate_of,
+                mean_intensity: mean_intensities[input_index],
+                page_diffs: std::mem::take(&mut page_diffs[index]),
                 page_size: PageSize {
-                    w: _img.width() as i32,
-                    h: _img.height() as i32,
+                    w: page_sizes[input_index].0 as i32,
+                    h: page_sizes[input_index].1 as i32,
                 },
-            };
-            output.images.push(image_status);
+            });
         }
+        for input_index in blank_images {
+            image_statuses[input_index] = Some(ImageStatus {
+                image_source: image_sources[input_index].clone(),
+                input_index,
+                code: CONFIG.image_status_code.blank,
+                message: "image is blank".to_string(),
+                page_index: None,
+                orientation: None,
+                mirrored: None,
+                part: None,
+                duplicate_of: None,
+                mean_intensity: mean_intensities[input_index],
+                page_diffs: Vec::new(),
+                page_size: PageSize {
+                    w: page_sizes[input_index].0 as i32,
+                    h: page_sizes[input_index].1 as i32,
+                },
+            });
+        }
+        output.images.extend(image_statuses.into_iter().flatten());
 
         processed_images_res
     }
 }
 
-/// 根据wh比例决定是否对图片进行90度旋转
-pub fn rotate_processed_image_90(model_size: &ModelSize, img: &mut ProcessedImages){
-    // 如果标注的长宽大小和图片的长宽大小关系不同，说明图片需要90度偏转
-    let flag_need_90 = (model_size.h > model_size.w) != (img.rgb.height() > img.rgb.width());
-    if flag_need_90{
-        rotate_processed_image(img, PI/2.0);
+/// 去掉四周后的平均灰度，扫描仪在纸张边缘留下的阴影不影响空白页判断
+fn calculate_mean_intensity(img: &ProcessedImages) -> f32 {
+    let (w, h) = (img.gray.width() as i32, img.gray.height() as i32);
+    let margin_x = (w as f32 * CONFIG.blank_page.border_ignore) as i32;
+    let margin_y = (h as f32 * CONFIG.blank_page.border_ignore) as i32;
+    let inner = Coordinate{x: margin_x, y: margin_y, w: w - 2 * margin_x, h: h - 2 * margin_y};
+    (1.0 - calculate_fill_rate(&img.integral_gray, &inner)) * 255.0
+}
+
+/// 小角度摆正之后的翻转方式，镜像为水平翻转
+/// 两种翻转可以交换顺序，结果相同
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Flip {
+    rotate_180: bool,
+    mirror: bool,
+}
+
+const FLIPS: [Flip; 4] = [
+    Flip{rotate_180: false, mirror: false},
+    Flip{rotate_180: true, mirror: false},
+    Flip{rotate_180: false, mirror: true},
+    Flip{rotate_180: true, mirror: true},
+];
+
+impl Flip {
+    /// 加上翻转后图片总的顺时针旋转角度
+    fn orientation(&self, quarter_orientation: i32) -> i32 {
+        if self.rotate_180 { (quarter_orientation + 180) % 360 } else { quarter_orientation }
+    }
+
+    /// 框翻转后的位置，图片宽高为w、h，翻转两次回到原位
+    fn coordinate(&self, coordinate: &Coordinate, w: i32, h: i32) -> Coordinate {
+        let x = if self.rotate_180 != self.mirror { w - coordinate.x - coordinate.w } else { coordinate.x };
+        let y = if self.rotate_180 { h - coordinate.y - coordinate.h } else { coordinate.y };
+        Coordinate{x, y, w: coordinate.w, h: coordinate.h}
+    }
+
+    /// 四个角定位点翻转后的位置，0，1，2，3对应左上，右上，左下，右下
+    /// 左右翻转时交换左右，上下翻转时交换上下
+    fn corners(&self, corners: &[Coordinate;4], w: i32, h: i32) -> [Coordinate;4] {
+        let swap = if self.rotate_180 != self.mirror { 1 } else { 0 } | if self.rotate_180 { 2 } else { 0 };
+        std::array::from_fn(|index| self.coordinate(&corners[index ^ swap], w, h))
+    }
+}
+
+/// 需要尝试的90度旋转，false为不旋转，true为顺时针旋转90度
+/// 图片和模板的长宽比能明确区分时只试一种，接近正方形时两种都试
+fn quarter_turn_candidates(model_size: &ModelSize, img: &ProcessedImages) -> Vec<bool> {
+    let model_ratio = (model_size.w as f32 / model_size.h as f32).ln();
+    let img_ratio = (img.rgb.width() as f32 / img.rgb.height() as f32).ln();
+    let mismatch = (img_ratio - model_ratio).abs();
+    let mismatch_turned = (img_ratio + model_ratio).abs();
+    let margin = CONFIG.image_baizheng.orientation_aspect_margin;
+    if mismatch + margin < mismatch_turned {
+        vec![false]
+    } else if mismatch_turned + margin < mismatch {
+        vec![true]
+    } else {
+        vec![false, true]
+    }
+}
+
+/// 图片一个方向的定位结果，四个角、小角度偏转和拟合结果，找不到定位点时为错误信息
+pub struct Location{
+    /// 是否先顺时针旋转90度再定位
+    pub quarter_turn: bool,
+    pub corners: Result<([Coordinate;4], f32, Option<MarkFit>), String>
+}
+
+/// 长宽比可能的方向分别按模板寻找定位点，拆分时用来判断图片是否是一页完整的page，摆正时直接使用
+pub fn locate_quarter_turns(img: &ProcessedImages, pages: &[Page]) -> Vec<Location> {
+    quarter_turn_candidates(&pages[0].model_size, img).into_iter().map(|quarter_turn| {
+        let corners = if quarter_turn {
+            locate_corners(&rotate_processed_image_clockwise_90(img), pages)
+        } else {
+            locate_corners(img, pages)
+        };
+        Location{quarter_turn, corners}
+    }).collect()
+}
+
+/// 按寻找到的定位点进行小角度摆正
+/// 输出摆正后的四个定位点和定位点拟合结果，并小角度摆正输入的图片
+fn rotate_to_location(img: &mut ProcessedImages, location: ([Coordinate;4], f32, Option<MarkFit>)) -> ([Coordinate;4], Option<MarkFit>){
+    let (corners, angle_radians1, mut mark_fit) = location;
+
+    // 旋转之前保存中心点
+    let center = MyPoint{x:(img.rgb.width()/2) as i32, y:(img.rgb.height()/2) as i32};
+
+    // 对图像进行旋转
+    rotate_processed_image(img, -angle_radians1);
+
+    // 对定位点进行旋转
+    let rotate_coordinate = |point: &Coordinate| {
+        let (new_x, new_y) = rotate_point(&MyPoint{x:point.x,y:point.y}, &center, -angle_radians1);
+        Coordinate{x:new_x,y:new_y,w:point.w,h:point.h}
+    };
+    let points: [Coordinate;4] = corners.map(|point| rotate_coordinate(&point));
+    if let Some(mark_fit) = mark_fit.as_mut() {
+        for mark in mark_fit.marks.iter_mut() {
+            *mark = rotate_coordinate(mark);
+        }
+    }
+    (points, mark_fit)
+}
+
+/// 寻找四个角的定位点，输出按图片中左上、右上、左下、右下排列的四个角、小角度偏转和拟合结果
+/// 优先按每个page的模板定位点布局拟合，取吻合定位点最多、残差最小的一个
+/// 拟合按透视变换进行，对边长度相差不超过10%的梯形畸变都能找到四个角，再大时定位点本身变形，候选点会被过滤
+/// 都拟合失败时退回按第一页x±y最值寻找四个角
+fn locate_corners(img: &ProcessedImages, pages: &[Page]) -> Result<([Coordinate;4], f32, Option<MarkFit>), String>{
+    let contours: Vec<Contour<i32>> = find_contours(&img.morphology);
+    let mut best: Option<([Coordinate;4], f32, MarkFit)> = None;
+    for (index, page) in pages.iter().enumerate() {
+        // 和前面的page布局相同时结果一样，不用重复拟合
+        if pages[..index].iter().any(|other| same_layout(other, page)) {
+            continue;
+        }
+        let expected_scale = img.rgb.width() as f32 / page.model_size.w as f32;
+        let candidates = score_mark_candidates(img, &contours, &page.model_points[0].coordinate, expected_scale);
+        let Some((corners, angle, fit)) = fit_marks_to_template(&candidates, page, expected_scale) else {continue};
+        if check_location(&corners, img, page, expected_scale).is_err() {
+            continue;
+        }
+        let better = match &best {
+            Some((_, _, best_fit)) => fit.marks.len() > best_fit.marks.len()
+                || (fit.marks.len() == best_fit.marks.len() && fit.residual < best_fit.residual),
+            None => true,
+        };
+        if better {
+            best = Some((corners, angle, fit));
+        }
+    }
+    if let Some((corners, angle, fit)) = best {
+        return Ok((corners, angle, Some(fit)));
+    }
+    let page = &pages[0];
+    let location_wh = (page.model_points[0].coordinate.w, page.model_points[0].coordinate.h);
+    let [lt, rt, ld, rd] = locate_corners_by_extremes(img, location_wh);
+    check_location(&[lt, rt, ld, rd], img, page, img.rgb.width() as f32 / page.model_size.w as f32)?;
+    // 根据定位点计算偏转角度
+    let angle = (rt.y as f32 - lt.y as f32).atan2(rt.x as f32 - lt.x as f32);
+    Ok(([lt, rt, ld, rd], angle, None))
+}
+
+/// 两个page的定位点布局相同
+fn same_layout(a: &Page, b: &Page) -> bool {
+    let layout = |page: &Page| -> Vec<(i32, i32, i32, i32)> {
+        page.model_points.iter().map(|point| (point.coordinate.x, point.coordinate.y, point.coordinate.w, point.coordinate.h)).collect()
+    };
+    a.card_columns == b.card_columns && a.model_size.w == b.model_size.w && a.model_size.h == b.model_size.h && layout(a) == layout(b)
+}
+
+/// 检查四个角的定位点是否可信
+/// 没找到定位点时坐标是初始的极大极小值，会超出图片；
+/// 找错定位点时四个角围成的区域和模板的大小、宽高比不符
+fn check_location(corners: &[Coordinate;4], img: &ProcessedImages, page: &Page, expected_scale: f32) -> Result<(), String> {
+    let (w, h) = (img.rgb.width() as i32, img.rgb.height() as i32);
+    if corners.iter().any(|c| c.x < 0 || c.y < 0 || c.x + c.w > w || c.y + c.h > h) {
+        return Err("location mark not found".to_string());
+    }
+    let [lt, rt, ld, rd] = corners;
+    let columns = page.card_columns as usize + 1;
+    let model_len = page.model_points.len();
+    let model_lt = page.model_points[0].coordinate;
+    let model_rt = page.model_points[columns - 1].coordinate;
+    let model_ld = page.model_points[model_len - columns].coordinate;
+    let model_w = (model_rt.x - model_lt.x) as f32;
+    let model_h = (model_ld.y - model_lt.y) as f32;
+    // 上下两边、左右两边各取平均
+    let real_w = ((rt.x - lt.x) + (rd.x - ld.x)) as f32 / 2.0;
+    let real_h = ((ld.y - lt.y) + (rd.y - rt.y)) as f32 / 2.0;
+    if real_w <= 0.0 || real_h <= 0.0 || model_w <= 0.0 || model_h <= 0.0 {
+        return Err("location marks are in wrong order".to_string());
+    }
+    if (real_w / (model_w * expected_scale) - 1.0).abs() > CONFIG.image_baizheng.mark_scale_tolerance {
+        return Err("location marks do not match model size".to_string());
+    }
+    if ((real_h / real_w) / (model_h / model_w) - 1.0).abs() > CONFIG.image_baizheng.location_aspect_tolerance {
+        return Err("location marks do not match model aspect ratio".to_string());
+    }
+    Ok(())
+}
+
+/// 候选定位点，按形状、实心程度和填涂率打分后过滤
+/// 形状：宽高比和标注定位点的接近程度
+/// 实心程度：轮廓面积和凸包面积之比，可以排除考号等文字笔画
+/// 填涂率：外接框内黑色像素的比例
+/// 输出按得分从高到低排列
+fn score_mark_candidates(img: &ProcessedImages, contours: &[Contour<i32>], mark: &Coordinate, expected_scale: f32) -> Vec<Coordinate> {
+    let expected_w = mark.w as f32 * expected_scale;
+    let expected_h = mark.h as f32 * expected_scale;
+    let mark_ratio = mark.w as f32 / mark.h.max(1) as f32;
+
+    let mut candidates = Vec::new();
+    for contour in contours.iter(){
+        let Some([lt_box, rt_box, ld_box]) = calculate_points_lt_rt_ld(&contour.points) else {continue};
+        let w = euclidean_distance((lt_box.x as f32,lt_box.y as f32), (rt_box.x as f32,rt_box.y as f32));
+        let h = euclidean_distance((lt_box.x as f32,lt_box.y as f32), (ld_box.x as f32,ld_box.y as f32));
+        // 大小和预期相差太多的直接排除
+        if w < expected_w * 0.5 || w > expected_w * 2.0 || h < expected_h * 0.5 || h > expected_h * 2.0 {
+            continue;
+        }
+        let ratio = w / h.max(1.0);
+        let shape = ratio.min(mark_ratio) / ratio.max(mark_ratio);
+        let hull_area = contour_area(&convex_hull(&contour.points));
+        if hull_area <= 0.0 {
+            continue;
+        }
+        let solidity = (contour_area(&contour.points) / hull_area) as f32;
+        let coordinate = Coordinate{x: lt_box.x, y: lt_box.y, w: w as i32, h: h as i32};
+        let fill = calculate_fill_rate(&img.integral_morphology, &coordinate);
+        let score = shape * solidity * fill;
+        if score >= CONFIG.image_baizheng.mark_min_score {
+            candidates.push((coordinate, score));
+        }
+    }
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+    candidates.into_iter().map(|(coordinate, _)| coordinate).collect()
+}
+
+/// 在候选定位点中找和模板定位点布局最吻合的一组
+/// 取模板的两条对角线，用得分最高的若干候选点两两作为对角线两端，求相似变换（缩放+旋转+平移），
+/// 两端距离和预期缩放不符的点对直接跳过，其余从相似变换出发，用吻合的点拟合仿射或透视变换以适应梯形畸变，
+/// 统计模板定位点变换后附近有候选点的个数，取最多的一组，残差相对定位点过大时拟合失败
+/// 没检测到的角按同一行和同一列吻合的定位点预测
+/// 允许图片倒置，输出按图片中左上、右上、左下、右下排列的四个角、小角度偏转和拟合结果
+fn fit_marks_to_template(candidates: &[Coordinate], page: &Page, expected_scale: f32) -> Option<([Coordinate;4], f32, MarkFit)> {
+    let columns = page.card_columns as usize + 1;
+    let model_len = page.model_points.len();
+    if model_len < columns * 2 || candidates.len() < 2 {
+        return None;
     }
+    let corner_indexes = [0, columns - 1, model_len - columns, model_len - 1];
+    let center = |coordinate: &Coordinate| {
+        (coordinate.x as f32 + coordinate.w as f32 / 2.0, coordinate.y as f32 + coordinate.h as f32 / 2.0)
+    };
+    let model_centers: Vec<(f32, f32)> = page.model_points.iter().map(|point| center(&point.coordinate)).collect();
+    let candidate_centers: Vec<(f32, f32)> = candidates.iter().map(center).collect();
+    let inlier_distance = page.model_points[0].coordinate.h as f32 * expected_scale * CONFIG.image_baizheng.mark_inlier_distance;
+
+    // 相似变换用复数表示：p' = a * p + b
+    let transform = |a: (f32, f32), b: (f32, f32), p: (f32, f32)| {
+        (a.0 * p.0 - a.1 * p.1 + b.0, a.0 * p.1 + a.1 * p.0 + b.1)
+    };
+    // 相似变换写成单应矩阵，和透视拟合共用匹配过程
+    let similarity_homography = |a: (f32, f32), b: (f32, f32)| [a.0, -a.1, b.0, a.1, a.0, b.1, 0.0, 0.0, 1.0];
+    // 离投影位置最近的候选点和距离
+    let nearest_candidate = |projected: (f32, f32)| {
+        candidate_centers.iter()
+            .map(|&candidate| euclidean_distance(projected, candidate))
+            .enumerate()
+            .filter(|(_, distance)| *distance <= inlier_distance)
+            .min_by(|x, y| x.1.total_cmp(&y.1))
+    };
+    // 每个模板定位点最近的候选点和距离，一个候选点只留给离它最近的模板定位点
+    // 透视拟合退化时会把多个定位点投影到一起，不去重会被当成全部吻合
+    let match_marks = |homography: &[f32; 9]| -> MarkMatches {
+        let mut matches: MarkMatches = model_centers.iter()
+            .map(|&model_center| apply_homography(homography, model_center).and_then(nearest_candidate))
+            .collect();
+        for index in 0..matches.len() {
+            let Some((candidate, distance)) = matches[index] else {continue};
+            let closer = matches.iter().enumerate().any(|(other, matched)| {
+                other != index && matches!(matched, Some((c, d)) if *c == candidate && (*d < distance || (*d == distance && other < index)))
+            });
+            if closer {
+                matches[index] = None;
+            }
+        }
+        matches
+    };
+    // 吻合的模板定位点和候选点中心
+    let matched_pairs = |matches: &[Option<(usize, f32)>], keep: &dyn Fn(usize) -> bool| -> PointPairs {
+        matches.iter().enumerate()
+            .filter(|(index, _)| keep(*index))
+            .filter_map(|(index, matched)| matched.map(|(candidate, _)| (model_centers[index], candidate_centers[candidate])))
+            .unzip()
+    };
+    let score = |matches: &[Option<(usize, f32)>]| {
+        let inliers: Vec<f32> = matches.iter().flatten().map(|(_, distance)| *distance).collect();
+        let residual = (inliers.iter().map(|d| d * d).sum::<f32>() / inliers.len().max(1) as f32).sqrt();
+        (inliers.len(), residual)
+    };
+
+    // 从相似变换出发，用吻合的点拟合仿射变换和透视变换，取吻合多的一个重新找吻合的点，吻合个数不再增加为止
+    // 手机拍照的梯形畸变下离中心远的定位点偏离相似变换，需要透视变换才能全部吻合
+    // 吻合的点少或大多共线时透视拟合会退化，先仿射拟合可以把吻合的点补够
+    let refine = |a: (f32, f32), b: (f32, f32)| -> ([f32; 9], MarkMatches) {
+        let mut homography = similarity_homography(a, b);
+        let mut matches = match_marks(&homography);
+        for _ in 0..CONFIG.image_baizheng.mark_refine_iterations {
+            let (model_matched, real_matched) = matched_pairs(&matches, &|_| true);
+            let refined = [fit_affine(&model_matched, &real_matched), fit_homography(&model_matched, &real_matched)]
+                .into_iter()
+                .flatten()
+                .map(|refined| (match_marks(&refined), refined))
+                .max_by(|x, y| {
+                    let (x_score, y_score) = (score(&x.0), score(&y.0));
+                    x_score.0.cmp(&y_score.0).then(y_score.1.total_cmp(&x_score.1))
+                });
+            let Some((refined_matches, refined)) = refined else {break};
+            let (count, refined_count) = (score(&matches).0, score(&refined_matches).0);
+            if refined_count < count {
+                break;
+            }
+            homography = refined;
+            matches = refined_matches;
+            if refined_count == count {
+                break;
+            }
+        }
+        (homography, matches)
+    };
+
+    // 只用得分最高的候选点作为对角线两端，吻合个数仍然统计全部候选点
+    let hypothesis_centers = &candidate_centers[..candidate_centers.len().min(CONFIG.image_baizheng.mark_max_candidates)];
+    // 最好的一组：(对角线确定的相似变换, 拟合的变换, 吻合结果, 吻合个数, 残差)
+    let mut best: Option<(Similarity, [f32; 9], MarkMatches, usize, f32)> = None;
+    for (m1, m2) in [(corner_indexes[0], corner_indexes[3]), (corner_indexes[1], corner_indexes[2])] {
+        let dm = (model_centers[m2].0 - model_centers[m1].0, model_centers[m2].1 - model_centers[m1].1);
+        let dm_norm = dm.0 * dm.0 + dm.1 * dm.1;
+        if dm_norm <= 0.0 {
+            continue;
+        }
+        let dm_length = dm_norm.sqrt();
+        for (i, ci) in hypothesis_centers.iter().enumerate() {
+            for (j, cj) in hypothesis_centers.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                let dc = (cj.0 - ci.0, cj.1 - ci.1);
+                if (euclidean_distance(*ci, *cj) / (dm_length * expected_scale) - 1.0).abs() > CONFIG.image_baizheng.mark_scale_tolerance {
+                    continue;
+                }
+                // a = dc / dm
+                let a = ((dc.0 * dm.0 + dc.1 * dm.1) / dm_norm, (dc.1 * dm.0 - dc.0 * dm.1) / dm_norm);
+                let scale = (a.0 * a.0 + a.1 * a.1).sqrt();
+                if (scale / expected_scale - 1.0).abs() > CONFIG.image_baizheng.mark_scale_tolerance {
+                    continue;
+                }
+                if tilt_angle(a).to_degrees().abs() > CONFIG.image_baizheng.mark_max_angle {
+                    continue;
+                }
+                let origin = transform(a, (0.0, 0.0), model_centers[m1]);
+                let b = (ci.0 - origin.0, ci.1 - origin.1);
+                let (homography, matches) = refine(a, b);
+                let (count, residual) = score(&matches);
+                let better = match best {
+                    Some((_, _, _, best_count, best_residual)) => count > best_count || (count == best_count && residual < best_residual),
+                    None => true,
+                };
+                if better {
+                    best = Some(((a, b), homography, matches, count, residual));
+                }
+            }
+        }
+    }
+    let ((a, b), homography, matches, count, residual) = best?;
+    if count < CONFIG.image_baizheng.mark_min_inliers {
+        return None;
+    }
+
+    // 用全部吻合的点最小二乘拟合相似变换，得到偏转角度、是否倒置和缩放
+    let (model_matched, real_matched) = matched_pairs(&matches, &|_| true);
+    let n = model_matched.len() as f32;
+    let model_mean = (model_matched.iter().map(|p| p.0).sum::<f32>() / n, model_matched.iter().map(|p| p.1).sum::<f32>() / n);
+    let real_mean = (real_matched.iter().map(|p| p.0).sum::<f32>() / n, real_matched.iter().map(|p| p.1).sum::<f32>() / n);
+    let (mut numerator, mut denominator) = ((0.0f32, 0.0f32), 0.0f32);
+    for (model, real) in model_matched.iter().zip(real_matched.iter()) {
+        let m = (model.0 - model_mean.0, model.1 - model_mean.1);
+        let r = (real.0 - real_mean.0, real.1 - real_mean.1);
+        // r * conj(m)
+        numerator.0 += r.0 * m.0 + r.1 * m.1;
+        numerator.1 += r.1 * m.0 - r.0 * m.1;
+        denominator += m.0 * m.0 + m.1 * m.1;
+    }
+    let (a, b) = if denominator > 0.0 {
+        let a = (numerator.0 / denominator, numerator.1 / denominator);
+        let origin = transform(a, (0.0, 0.0), model_mean);
+        (a, (real_mean.0 - origin.0, real_mean.1 - origin.1))
+    } else {
+        (a, b)
+    };
+    // 残差远大于定位点时找到的不是定位点，或纸张变形超出了透视变换能描述的范围
+    let model_mark = page.model_points[0].coordinate;
+    let scale = (a.0 * a.0 + a.1 * a.1).sqrt();
+    if residual > model_mark.w.min(model_mark.h) as f32 * scale * CONFIG.image_baizheng.mark_max_residual {
+        return None;
+    }
+
+    // 四个角优先用检测到的定位点
+    // 没检测到的用同一行和同一列吻合的定位点拟合透视变换预测位置，行列是直线，透视变换下仍是直线
+    // 吻合的点不够四个时用仿射变换，不够三个时用整体的拟合结果，预测位置附近有候选点时用候选点
+    let model_corners = corner_indexes.map(|index| {
+        if let Some((candidate, _)) = matches[index] {
+            return candidates[candidate];
+        }
+        let (row, column) = (index / columns, index % columns);
+        let (model_neighbours, real_neighbours) = matched_pairs(
+            &matches, &|other| other != index && (other / columns == row || other % columns == column)
+        );
+        let local = fit_homography(&model_neighbours, &real_neighbours)
+            .or_else(|| fit_affine(&model_neighbours, &real_neighbours))
+            .unwrap_or(homography);
+        let predicted = apply_homography(&local, model_centers[index])
+            .or_else(|| apply_homography(&homography, model_centers[index]))
+            .unwrap_or_else(|| transform(a, b, model_centers[index]));
+        if let Some((candidate, _)) = nearest_candidate(predicted) {
+            return candidates[candidate];
+        }
+        let mark = page.model_points[index].coordinate;
+        let (w, h) = (mark.w as f32 * scale, mark.h as f32 * scale);
+        Coordinate{x: (predicted.0 - w / 2.0) as i32, y: (predicted.1 - h / 2.0) as i32, w: w as i32, h: h as i32}
+    });
+    // 倒置时模板的右下角在图片的左上
+    let flipped = a.1.atan2(a.0).abs() > PI / 2.0;
+    let [lt, rt, ld, rd] = model_corners;
+    let corners = if flipped { [rd, ld, rt, lt] } else { [lt, rt, ld, rd] };
+    let marks = matches.iter().flatten().map(|(candidate, _)| candidates[*candidate]).collect();
+    Some((corners, tilt_angle(a), MarkFit{ marks, residual }))
 }
 
+/// 用复数表示的相似变换系数(a, b)，p' = a * p + b
+type Similarity = ((f32, f32), (f32, f32));
 
+/// 每个模板定位点吻合的候选点序号和距离，没吻合为None
+type MarkMatches = Vec<Option<(usize, f32)>>;
 
-/// 靠图片寻找定位点并进行小角度摆正
-/// 输出四个定位点并小角度摆正输入的图片
-fn generate_location_and_rotate(img: &mut ProcessedImages, location_wh: (i32, i32)) -> [Coordinate;4]{
+/// 一一对应的模板点和图片点
+type PointPairs = (Vec<(f32, f32)>, Vec<(f32, f32)>);
+
+/// 相似变换的旋转角去掉倒置的180度，得到小角度偏转
+fn tilt_angle(a: (f32, f32)) -> f32 {
+    let angle = a.1.atan2(a.0);
+    if angle > PI / 2.0 {
+        angle - PI
+    } else if angle < -PI / 2.0 {
+        angle + PI
+    } else {
+        angle
+    }
+}
+
+/// 按x±y最值寻找四个角的定位点，不旋转图片
+fn locate_corners_by_extremes(img: &ProcessedImages, location_wh: (i32, i32)) -> [Coordinate;4]{
     // todo: 定位点过滤补丁，后面需要优化
     let w = img.rgb.width();
     let lt_x_must_less = ((w as f32) / (4 as f32)) as i32;
@@ -189,129 +726,129 @@ fn generate_location_and_rotate(img: &mut ProcessedImages, location_wh: (i32, i3
         }
     }
 
-    // println!("{lt:?}");
-    // println!("{rt:?}");
-    // println!("{ld:?}");
-    // println!("{rd:?}");
-    // let mut image = img.rgb.clone();
-    // draw_filled_circle_mut(&mut image, (lt.x,lt.y), 5, Rgb([0,0,255]));
-    // draw_filled_circle_mut(&mut image, (rt.x,rt.y), 5, Rgb([0,0,255]));
-    // draw_filled_circle_mut(&mut image, (ld.x,ld.y), 5, Rgb([0,0,255]));
-    // draw_filled_circle_mut(&mut image, (rd.x,rd.y), 5, Rgb([0,0,255]));
-    // image.save("dev/test_data/output_view_location.jpg");
+    [lt, rt, ld, rd]
+}
 
-    // 根据定位点计算偏转角度
-    // todo: 如果答题卡被折过，这种方法会有误差。
-    // 后面可以增加一种对定位点位置的判断，猜测纸张是否可能被折过
-    // 如果被折过，使用左侧两点后右侧两点分别对办张图片摆正，两边的框分开定位。
-    let angle_radians1 = (rt.y as f32 - lt.y as f32).atan2(rt.x as f32 - lt.x as f32);
-    // let angle_radians2 = (ld.y as f32 - lt.y as f32).atan2(ld.x as f32 - lt.x as f32);
 
-    // 旋转之前保存中心点
-    let center = MyPoint{x:(img.rgb.width()/2) as i32, y:(img.rgb.height()/2) as i32};
+/// 检测page的全部定位点，输出和page.model_points一一对应
+/// 先用四个角的定位点预测每个定位点的位置，再取搜索半径内最近的、形状和定位点相似的轮廓
+fn locate_all_model_points(page: &Page, img_and_model_points: &ProcessedImagesAndModelPoints) -> Vec<Option<Coordinate>> {
+    let reference_model_points = ReferenceModelPoints{
+        model_points: page.model_points_4.as_ref().expect("model_points_4 is None"),
+        real_model_points: &img_and_model_points.real_model_points,
+        model_points_all: &[],
+        real_model_points_all: &[],
+        grid_columns: 0,
+        folded: false,
+    };
 
-    // 对图像进行旋转
-    rotate_processed_image(img, -angle_radians1);
+    // 所有像定位点的轮廓，位置和宽高的算法与四个角的定位点一致
+    let contours: Vec<Contour<i32>> = find_contours(&img_and_model_points.img.morphology);
+    let mut candidates: Vec<Coordinate> = Vec::new();
+    for contour in contours.iter(){
+        let Some([lt_box, rt_box, ld_box]) = calculate_points_lt_rt_ld(&contour.points) else {continue};
+        let w = euclidean_distance((lt_box.x as f32,lt_box.y as f32), (rt_box.x as f32,rt_box.y as f32)) as i32;
+        let h = euclidean_distance((lt_box.x as f32,lt_box.y as f32), (ld_box.x as f32,ld_box.y as f32)) as i32;
+        candidates.push(Coordinate{x: lt_box.x, y: lt_box.y, w, h});
+    }
 
-    // 对定位点进行旋转
-    let mut points: [Coordinate;4] = [Coordinate{x:0,y:0,w:0,h:0};4];
-    for (i,point) in [lt, rt, ld, rd].iter().enumerate(){
-        let (new_x, new_y) = rotate_point(&MyPoint{x:point.x,y:point.y}, &center, -angle_radians1);
-        points[i] = Coordinate{x:new_x,y:new_y,w:point.w,h:point.h};
+    page.model_points.iter()
+        .map(|model_point| {
+            let predicted = generate_real_coordinate_with_model_points(&reference_model_points, &model_point.coordinate);
+            let radius = predicted.h.max(1) as f32 * CONFIG.image_baizheng.model_point_search_radius;
+            candidates.iter()
+                .filter(|candidate| {
+                    CONFIG.image_baizheng.model_point_wh_cosine_similarity <= cosine_similarity(
+                        &[candidate.w as f32, candidate.h as f32],
+                        &[model_point.coordinate.w as f32, model_point.coordinate.h as f32]
+                    )
+                })
+                .map(|candidate| {
+                    (candidate, euclidean_distance((candidate.x as f32, candidate.y as f32), (predicted.x as f32, predicted.y as f32)))
+                })
+                .filter(|(_, distance)| *distance <= radius)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(candidate, _)| *candidate)
+        })
+        .collect()
+}
+
+
+/// 根据全部定位点判断纸张是否被折过
+/// 透视变换下同一行、同一列的定位点仍然共线，手机拍照的梯形畸变不会被误判
+/// 折痕经过的定位点会偏离两侧相邻定位点的连线，检查每个两侧都检测到的定位点和两侧连线的夹角
+/// 至少三列才能检查行，至少三行才能检查列
+fn detect_fold(page: &Page, real_model_points_all: &[Option<Coordinate>]) -> bool {
+    let columns = page.card_columns as usize + 1;
+    if columns == 0 || real_model_points_all.len() != page.model_points.len() {
+        return false;
+    }
+    let rows = page.model_points.len() / columns;
+    if rows * columns != page.model_points.len() {
+        return false;
     }
-    points
+    let center = |index: usize| real_model_points_all[index].map(|point| (point.x as f32 + point.w as f32 / 2.0, point.y as f32 + point.h as f32 / 2.0));
+    // 前一段和后一段连线方向的夹角（度）
+    let bend = |before: usize, middle: usize, after: usize| -> Option<f32> {
+        let (p0, p1, p2) = (center(before)?, center(middle)?, center(after)?);
+        let angle = (p2.1 - p1.1).atan2(p2.0 - p1.0) - (p1.1 - p0.1).atan2(p1.0 - p0.0);
+        // 归一化到[-180, 180]
+        Some((angle.sin().atan2(angle.cos())).to_degrees().abs())
+    };
+    let row_bends = (0..rows).flat_map(|row| (1..columns.saturating_sub(1)).map(move |column| row * columns + column))
+        .filter_map(|index| bend(index - 1, index, index + 1));
+    let column_bends = (1..rows.saturating_sub(1)).flat_map(|row| (0..columns).map(move |column| row * columns + column))
+        .filter_map(|index| bend(index - columns, index, index + columns));
+    row_bends.chain(column_bends).any(|angle| angle > CONFIG.image_baizheng.fold_angle)
 }
 
 
 /// 输入的图片已经是经过小角度摆正+90度摆正的图片
-/// 该函数根据页面点的向量距离对page和image进行匹配
-/// 匹配成功的img直接进行180大角度摆正
+/// 该函数根据页面点的向量距离计算page和按flip翻转后的image的差异，差异越小越匹配
 fn match_page_and_img(
-    baizheng_info: &RecInfoBaizheng, img_and_model_points: &ProcessedImagesAndModelPoints
-) -> bool {
-    
-    // 输入图片可能是需要180翻转的，根据真实页码点填涂率和标注页码点填涂率的距离确定
+    baizheng_info: &RecInfoBaizheng, img_and_model_points: &ProcessedImagesAndModelPoints, flip: Flip
+) -> f32 {
+    let img = &img_and_model_points.img;
+    let real_model_points = flip.corners(
+        &img_and_model_points.real_model_points, img.rgb.width() as i32, img.rgb.height() as i32
+    );
+    // 输入图片可能是需要180翻转或镜像的，根据真实页码点填涂率和标注页码点填涂率的距离确定
     let diff = calculate_page_img_diff(
         baizheng_info.page_number_points,
         &ReferenceModelPoints{
             model_points: baizheng_info.model_points,
-            real_model_points: &img_and_model_points.real_model_points,
+            real_model_points: &real_model_points,
+            // 匹配时还没有检测全部定位点，只用四个角
+            model_points_all: &[],
+            real_model_points_all: &[],
+            grid_columns: 0,
+            folded: false,
         },
-        &img_and_model_points.img.integral_morphology
+        &img.integral_morphology,
+        flip
     );
 
-    // 距离足够小说明匹配成功
     #[cfg(debug_assertions)]
     {
         println!("{diff}");
     }
-    // todo：匹配率作为报错信息返回
-    if diff <= CONFIG.image_baizheng.page_number_diff{
-        return true;
-    }
-
-    return false;
+    diff
 }
 
-/// 翻转180,会修改原图片,需要提前clone
-fn rotate_img_and_model_points_180(
-    img_and_model_points: &mut ProcessedImagesAndModelPoints
+/// 按flip翻转图片、四个角定位点和检测到的定位点，会修改原图片,需要提前clone
+fn flip_img_and_model_points(
+    img_and_model_points: &mut ProcessedImagesAndModelPoints,
+    flip: Flip
 ){
-    // 翻转中心
-    let center = MyPoint{
-        x: (img_and_model_points.img.rgb.width() / 2) as i32,
-        y: (img_and_model_points.img.rgb.height() / 2) as i32,
-    };
-
-    // 0，1，2，3对应左上，右上，左下，右下
-    // 0旋转180翻到3，1旋转180放到2，2旋转180放到1，3旋转180放到0
-    let real_point_0 = img_and_model_points.real_model_points[0];
-    let real_point_1 = img_and_model_points.real_model_points[1];
-    let real_point_2 = img_and_model_points.real_model_points[2];
-    let real_point_3 = img_and_model_points.real_model_points[3];
-
-    // 要翻转的不是每个定位点的左上xy坐标，而是右下的x+w,y+h
-    // 因为180之后每个定位点的右下变成了左上
-    let ((x0,y0), w0, h0) = (rotate_point(
-        &MyPoint{
-            x:real_point_3.x + real_point_3.w,
-            y:real_point_3.y + real_point_3.h,
-        },
-        &center, PI,
-    ),real_point_3.w, real_point_3.h);
-
-    let ((x1,y1), w1, h1) = (rotate_point(
-        &MyPoint{
-            x:real_point_2.x + real_point_2.w,
-            y:real_point_2.y + real_point_2.h,
-        },
-        &center, PI,
-    ), real_point_2.w,real_point_2.h);
-
-    let ((x2,y2), w2, h2) = (rotate_point(
-        &MyPoint{
-            x:real_point_1.x + real_point_1.w,
-            y:real_point_1.y + real_point_1.h,
-        },
-        &center, PI,
-    ), real_point_1.w, real_point_1.h);
-
-    let ((x3,y3), w3, h3) = (rotate_point(
-        &MyPoint{
-            x:real_point_0.x + real_point_0.w,
-            y:real_point_0.y + real_point_0.h,
-        },
-        &center, PI,
-    ), real_point_0.w, real_point_0.h);
-
-    img_and_model_points.real_model_points[0] = Coordinate{x:x0,y:y0,w:w0,h:h0};
-    img_and_model_points.real_model_points[1] = Coordinate{x:x1,y:y1,w:w1,h:h1};
-    img_and_model_points.real_model_points[2] = Coordinate{x:x2,y:y2,w:w2,h:h2};
-    img_and_model_points.real_model_points[3] = Coordinate{x:x3,y:y3,w:w3,h:h3};
-
-    // 图片旋转180
-    rotate_processed_image(&mut img_and_model_points.img, PI);
-
+    let w = img_and_model_points.img.rgb.width() as i32;
+    let h = img_and_model_points.img.rgb.height() as i32;
+    img_and_model_points.real_model_points = flip.corners(&img_and_model_points.real_model_points, w, h);
+    if let Some(mark_fit) = img_and_model_points.mark_fit.as_mut() {
+        for mark in mark_fit.marks.iter_mut() {
+            *mark = flip.coordinate(mark, w, h);
+        }
+    }
+    flip_processed_image(&mut img_and_model_points.img, flip.rotate_180, flip.mirror);
 }
 
 
@@ -321,7 +858,10 @@ fn calculate_page_img_diff(
     page_number_points: &Vec<PageNumberPoint>,
     reference_model_points: &ReferenceModelPoints,
     img: &ImageBuffer<Luma<i64>, Vec<i64>>,
+    flip: Flip,
 ) -> f32 {
+    // 积分图比原图多一行一列
+    let (w, h) = (img.width() as i32 - 1, img.height() as i32 - 1);
     // 获取标注的pagenumber填涂向量
     let mut page_number_fill_rates = Vec::new();
     let mut real_page_number_coordinates: Vec<Coordinate> = Vec::new();
@@ -331,7 +871,8 @@ fn calculate_page_img_diff(
             &page_number.coordinate
         );
         page_number_fill_rates.push(page_number.fill_rate);
-        real_page_number_coordinates.push(real_coordinate);
+        // 得到的是翻转后图片上的位置，翻转回原图取填涂率，不用真的翻转图片
+        real_page_number_coordinates.push(flip.coordinate(&real_coordinate, w, h));
     }
     // 计算距离
     let difference = calculate_page_number_difference(img, &real_page_number_coordinates, &page_number_fill_rates);
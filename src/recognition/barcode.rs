@@ -1,16 +1,129 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use rxing::{
-    common::HybridBinarizer,
+    common::{GlobalHistogramBinarizer, HybridBinarizer},
     multi::{GenericMultipleBarcodeReader, MultipleBarcodeReader},
-    BinaryBitmap, DecodeHintType, DecodeHintValue,
+    BarcodeFormat, BinaryBitmap, DecodeHintType, DecodeHintValue,
     MultiUseMultiFormatReader,
     BufferedImageLuminanceSource,
+    RXingResult, RXingResultMetadataType, RXingResultMetadataValue,
 };
-use image::DynamicImage;
-use crate::{models::{engine_rec::{ProcessedImages, ProcessedImagesAndModelPoints}, rec_result::Value}, recognition::engine::Engine};
+use image::{imageops::{crop_imm, FilterType}, DynamicImage, GrayImage};
+use crate::{models::{engine_rec::ProcessedImages, rec_result::{BarcodeValue, Value}}, recognition::engine::Engine};
+use crate::config::CONFIG;
 use crate::models::scan_json::Coordinate;
-use crate::my_utils::image::crop_image;
-pub fn decode_barcode(img: &DynamicImage, coor: Coordinate) -> Option<String> {
+use crate::my_utils::image::clip_coordinate;
+
+/// 二维码识别的码制
+pub const QRCODE_FORMATS: [BarcodeFormat; 2] = [BarcodeFormat::QR_CODE, BarcodeFormat::DATA_MATRIX];
+/// 条形码识别的码制
+pub const BARCODE_FORMATS: [BarcodeFormat; 4] = [
+    BarcodeFormat::CODE_128, BarcodeFormat::CODE_39, BarcodeFormat::EAN_13, BarcodeFormat::EAN_8
+];
+
+/// 识别失败后的重试步骤，按顺序尝试，识别到就停止
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DecodeAttempt {
+    /// 原图
+    Original,
+    /// 换成全局直方图二值化，适合光照均匀但对比度低的图
+    GlobalHistogram,
+    /// 放大，适合低分辨率扫描
+    Upscale,
+    /// 锐化，适合拍照模糊
+    Unsharpen,
+    /// 旋转90度
+    Rotate90,
+    /// 扩大截取范围，适合条码超出标注框
+    Widen,
+}
+
+const DECODE_ATTEMPTS: [DecodeAttempt; 6] = [
+    DecodeAttempt::Original,
+    DecodeAttempt::GlobalHistogram,
+    DecodeAttempt::Upscale,
+    DecodeAttempt::Unsharpen,
+    DecodeAttempt::Rotate90,
+    DecodeAttempt::Widen,
+];
+
+impl DecodeAttempt {
+    fn name(&self) -> &'static str {
+        match self {
+            DecodeAttempt::Original => "original",
+            DecodeAttempt::GlobalHistogram => "global_histogram",
+            DecodeAttempt::Upscale => "upscale",
+            DecodeAttempt::Unsharpen => "unsharpen",
+            DecodeAttempt::Rotate90 => "rotate90",
+            DecodeAttempt::Widen => "widen",
+        }
+    }
+}
+
+/// 只尝试formats中的码制，限制码制可以减少误识别并加快速度
+/// 原图识别不到时依次换二值化方法、放大、锐化、旋转、扩大截取范围重试
+/// 每个结果的attempt记录识别成功的那一步
+/// 输出框内识别到的所有码，按从上到下、从左到右排序，位置已换算回页面坐标
+pub fn decode_barcode(img: &GrayImage, coor: Coordinate, formats: &[BarcodeFormat]) -> Vec<BarcodeValue> {
+    for attempt in DECODE_ATTEMPTS {
+        // 截取的框，扩大范围时框的左上角也会变
+        let crop_coordinate = if attempt == DecodeAttempt::Widen {
+            let margin_x = (coor.w as f32 * CONFIG.barcode.widen_ratio) as i32;
+            let margin_y = (coor.h as f32 * CONFIG.barcode.widen_ratio) as i32;
+            Coordinate{x: coor.x - margin_x, y: coor.y - margin_y, w: coor.w + 2 * margin_x, h: coor.h + 2 * margin_y}
+        } else {
+            coor
+        };
+        // 框在图片外，超出图片的部分裁掉后框的左上角也会变
+        let Some(crop_coordinate) = clip_coordinate(&crop_coordinate, img.width(), img.height()) else {
+            return Vec::new();
+        };
+        // 只转换截取的部分，不复制整页
+        let crop = DynamicImage::ImageLuma8(crop_imm(
+            img, crop_coordinate.x as u32, crop_coordinate.y as u32, crop_coordinate.w as u32, crop_coordinate.h as u32
+        ).to_image());
+        let crop_h = crop.height() as f32;
+        let scale = CONFIG.barcode.upscale as f32;
+        let crop = match attempt {
+            DecodeAttempt::Upscale => crop.resize(
+                crop.width() * CONFIG.barcode.upscale, crop.height() * CONFIG.barcode.upscale, FilterType::CatmullRom
+            ),
+            DecodeAttempt::Unsharpen => crop.unsharpen(CONFIG.barcode.unsharpen_sigma, CONFIG.barcode.unsharpen_threshold),
+            DecodeAttempt::Rotate90 => crop.rotate90(),
+            _ => crop,
+        };
+
+        let results = decode_crop(crop, formats, attempt == DecodeAttempt::GlobalHistogram);
+        if results.is_empty() {
+            continue;
+        }
+        // 把处理后图上的点换算回截取框里
+        let to_crop = |x: f32, y: f32| -> (f32, f32) {
+            match attempt {
+                DecodeAttempt::Upscale => (x / scale, y / scale),
+                // 顺时针旋转90度的逆变换
+                DecodeAttempt::Rotate90 => (y, crop_h - 1.0 - x),
+                _ => (x, y),
+            }
+        };
+        return results.iter()
+            .map(|result| to_barcode_value(result, &crop_coordinate, &to_crop, attempt))
+            .collect();
+    }
+    Vec::new()
+}
+
+/// 整页查找二维码或条形码，输出第一个码的内容，用于确定图片所属的模板
+/// 整页图很大且反面通常没有码，只尝试两种二值化，不走完整的重试
+pub fn decode_page_code(img: &DynamicImage) -> Option<String> {
+    let formats: Vec<BarcodeFormat> = QRCODE_FORMATS.iter().chain(BARCODE_FORMATS.iter()).copied().collect();
+    [false, true].into_iter()
+        .map(|global_histogram| decode_crop(img.clone(), &formats, global_histogram))
+        .find(|results| !results.is_empty())
+        .and_then(|results| results.first().map(|result| result.getText().to_string()))
+}
+
+/// 单次识别，没有找到条码是正常情况，不能panic
+fn decode_crop(crop: DynamicImage, formats: &[BarcodeFormat], global_histogram: bool) -> Vec<RXingResult> {
     let multi_format_reader = MultiUseMultiFormatReader::default();
     let mut scanner = GenericMultipleBarcodeReader::new(multi_format_reader);
     let mut hints = HashMap::new();
@@ -18,28 +131,108 @@ pub fn decode_barcode(img: &DynamicImage, coor: Coordinate) -> Option<String> {
     hints
         .entry(DecodeHintType::TRY_HARDER)
         .or_insert(DecodeHintValue::TryHarder(true));
-    let results = scanner.decode_multiple_with_hints(
-        &mut BinaryBitmap::new(HybridBinarizer::new(BufferedImageLuminanceSource::new(crop_image(img, coor)))),
-        &hints,
-    ).expect("decodes");
-    for result in results {
-        //todo 需要处理识别多个结果
-        if !result.getText().is_empty() {
-            return Some(result.getText().to_string());
+    hints
+        .entry(DecodeHintType::POSSIBLE_FORMATS)
+        .or_insert(DecodeHintValue::PossibleFormats(formats.iter().copied().collect::<HashSet<BarcodeFormat>>()));
+
+    let source = BufferedImageLuminanceSource::new(crop);
+    let results = if global_histogram {
+        scanner.decode_multiple_with_hints(&mut BinaryBitmap::new(GlobalHistogramBinarizer::new(source)), &hints)
+    } else {
+        scanner.decode_multiple_with_hints(&mut BinaryBitmap::new(HybridBinarizer::new(source)), &hints)
+    };
+    let Ok(results) = results else {
+        return Vec::new();
+    };
+    let mut results: Vec<RXingResult> = results.into_iter()
+        .filter(|result| !result.getText().is_empty())
+        .collect();
+    results.sort_by_key(|result| {
+        let points = result.getPoints();
+        let top = points.iter().map(|p| p.y as i32).min().unwrap_or(0);
+        let left = points.iter().map(|p| p.x as i32).min().unwrap_or(0);
+        (top, left)
+    });
+    results
+}
+
+/// 整理rxing的识别结果
+/// crop_coordinate是识别时截取的框，to_crop把识别图上的点换算回截取框，用来得到码在页面上的位置
+fn to_barcode_value(
+    result: &RXingResult,
+    crop_coordinate: &Coordinate,
+    to_crop: &dyn Fn(f32, f32) -> (f32, f32),
+    attempt: DecodeAttempt
+) -> BarcodeValue {
+    let points: Vec<(f32, f32)> = result.getPoints().iter().map(|p| to_crop(p.x, p.y)).collect();
+    let orientation = match result.getRXingResultMetadata().get(&RXingResultMetadataType::ORIENTATION) {
+        Some(RXingResultMetadataValue::Orientation(orientation)) => Some(*orientation),
+        // 二维码的角点从码的左上开始顺时针排列，用左上到右上的方向作为旋转角度
+        _ if *result.getBarcodeFormat() == BarcodeFormat::QR_CODE && points.len() >= 4 => {
+            let angle = (points[1].1 - points[0].1).atan2(points[1].0 - points[0].0).to_degrees();
+            Some((angle.round() as i32).rem_euclid(360))
         }
+        _ => None,
+    };
+    // 一维码只有扫描线上的两个端点，外接框高度可能为0
+    let coordinate = if points.is_empty() {
+        None
+    } else {
+        let left = points.iter().map(|p| p.0).fold(f32::MAX, f32::min) as i32;
+        let top = points.iter().map(|p| p.1).fold(f32::MAX, f32::min) as i32;
+        let right = points.iter().map(|p| p.0).fold(f32::MIN, f32::max) as i32;
+        let bottom = points.iter().map(|p| p.1).fold(f32::MIN, f32::max) as i32;
+        Some(Coordinate{
+            x: crop_coordinate.x + left,
+            y: crop_coordinate.y + top,
+            w: right - left,
+            h: bottom - top,
+        })
+    };
+    BarcodeValue{
+        text: result.getText().to_string(),
+        format: result.getBarcodeFormat().to_string(),
+        orientation,
+        coordinate,
+        attempt: attempt.name().to_string(),
     }
-    return Option::None;
 }
 
 
-
 pub trait RecBarcode{
     /// 条形码识别
-    fn rec_barcode(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<Value>;
+    /// 输出识别结果和置信度，另外输出单个条码的码制、位置和识别步骤
+    fn rec_barcode(&self, img: &ProcessedImages, coordinate: &Coordinate) -> (Option<(Value, f32)>, Option<BarcodeValue>);
+    /// 二维码识别
+    /// 输出识别结果和置信度
+    fn rec_qrcode(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<(Value, f32)>;
 }
 
 impl RecBarcode for Engine {
-    fn rec_barcode(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<Value> {
-        None
+    /// 一个条码输出内容Value::String，码制、位置和识别步骤单独输出
+    /// 多个条码输出Value::List，每个是带码制和位置的Value::Barcode
+    /// 没有识别到输出None
+    fn rec_barcode(&self, img: &ProcessedImages, coordinate: &Coordinate) -> (Option<(Value, f32)>, Option<BarcodeValue>) {
+        let mut values = decode_barcode(&img.gray, *coordinate, &BARCODE_FORMATS);
+        // 条码自带校验，解出来的结果是可信的
+        match values.len() {
+            0 => (None, None),
+            1 => {
+                let value = values.pop().expect("values is empty");
+                (Some((Value::String(value.text.clone()), 1.0)), Some(value))
+            }
+            _ => (Some((Value::List(values.into_iter().map(Value::Barcode).collect()), 1.0)), None),
+        }
     }
-}
\ No newline at end of file
+
+    /// 一个二维码输出带码制、方向和位置的Value::Barcode，多个输出Value::List
+    /// 没有识别到输出None
+    fn rec_qrcode(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<(Value, f32)> {
+        let mut values = decode_barcode(&img.gray, *coordinate, &QRCODE_FORMATS);
+        match values.len() {
+            0 => None,
+            1 => values.pop().map(|value| (Value::Barcode(value), 1.0)),
+            _ => Some((Value::List(values.into_iter().map(Value::Barcode).collect()), 1.0)),
+        }
+    }
+}
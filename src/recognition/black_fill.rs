@@ -1,15 +1,99 @@
-use imageproc::integral_image::sum_image_pixels;
-
-use crate::{models::{engine_rec::ProcessedImages, rec_result::Value, scan_json::Coordinate}, recognition::engine::Engine};
+use crate::{models::{engine_rec::{FillStatistics, ProcessedImages}, rec_result::Value, scan_json::Coordinate}, recognition::engine::Engine};
+use crate::config::CONFIG;
+use crate::my_utils::image::calculate_fill_rate;
+use crate::my_utils::math::two_cluster_split;
 
 pub trait RecBlackFill{
-    /// 填涂识别
-    fn rec_black_fill(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<Value>;
+    /// 填涂识别，输出识别结果和置信度
+    fn rec_black_fill(&self, img: &ProcessedImages, coordinate: &Coordinate, statistics: &FillStatistics) -> Option<(Value, f32)>;
+    /// 按已经算出的填涂率输出识别结果和置信度
+    fn black_fill_value(&self, fill_rate: f32, statistics: &FillStatistics) -> (Value, f32);
+    /// 填涂率
+    fn black_fill_rate(&self, img: &ProcessedImages, coordinate: &Coordinate) -> f32;
+    /// 根据整页统计判断选项的填涂状态
+    fn black_fill_status(&self, fill_rate: f32, statistics: &FillStatistics) -> u8;
+    /// 填涂率离本页分界越远置信度越高
+    fn black_fill_confidence(&self, fill_rate: f32, statistics: &FillStatistics) -> f32;
+    /// 统计整页选项的填涂率，得到本页的填涂分界
+    /// page_number_rates是页码点的(实际填涂率, 标注填涂率)
+    fn black_fill_statistics(&self, fill_rates: &[f32], page_number_rates: &[(f32, f32)]) -> FillStatistics;
 }
 
 impl RecBlackFill for Engine {
-    fn rec_black_fill(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<Value>{
-        // sum_image_pixels(integral_image, left, top, right, bottom)
-        None
+    /// 填涂输出1，其他状态输出0
+    fn rec_black_fill(&self, img: &ProcessedImages, coordinate: &Coordinate, statistics: &FillStatistics) -> Option<(Value, f32)>{
+        Some(self.black_fill_value(self.black_fill_rate(img, coordinate), statistics))
+    }
+
+    fn black_fill_value(&self, fill_rate: f32, statistics: &FillStatistics) -> (Value, f32) {
+        let confidence = self.black_fill_confidence(fill_rate, statistics);
+        if self.black_fill_status(fill_rate, statistics) == CONFIG.fill_status.filled {
+            return (Value::Integer(1), confidence);
+        }
+        (Value::Integer(0), confidence)
+    }
+
+    fn black_fill_rate(&self, img: &ProcessedImages, coordinate: &Coordinate) -> f32 {
+        // 形态学处理会去掉选项框的细边框和印刷字母，只留下大块的填涂
+        calculate_fill_rate(&img.integral_morphology, coordinate)
+    }
+
+    fn black_fill_status(&self, fill_rate: f32, statistics: &FillStatistics) -> u8 {
+        // 把填涂率换算到整页空白和填涂之间的相对位置
+        let ratio = (fill_rate - statistics.blank) / (statistics.filled - statistics.blank);
+        // 分界以上只区分填涂和存疑，分界以下区分空白、擦除和存疑
+        if fill_rate >= statistics.threshold {
+            if ratio >= CONFIG.black_fill.filled_ratio {
+                return CONFIG.fill_status.filled;
+            }
+            return CONFIG.fill_status.ambiguous;
+        }
+        match ratio {
+            r if r < CONFIG.black_fill.erased_ratio => CONFIG.fill_status.blank,
+            r if r < CONFIG.black_fill.ambiguous_ratio => CONFIG.fill_status.erased,
+            _ => CONFIG.fill_status.ambiguous,
+        }
     }
-}
\ No newline at end of file
+
+    fn black_fill_confidence(&self, fill_rate: f32, statistics: &FillStatistics) -> f32 {
+        // 到达本页空白或填涂的均值即完全可信
+        let distance = if fill_rate >= statistics.threshold {
+            (fill_rate - statistics.threshold) / (statistics.filled - statistics.threshold).max(f32::EPSILON)
+        } else {
+            (statistics.threshold - fill_rate) / (statistics.threshold - statistics.blank).max(f32::EPSILON)
+        };
+        distance.clamp(0.0, 1.0)
+    }
+
+    fn black_fill_statistics(&self, fill_rates: &[f32], page_number_rates: &[(f32, f32)]) -> FillStatistics {
+        // 页码点和选项一起分成空白、填涂两类，保证整页没人填涂时也有填涂的样本
+        let mut population = fill_rates.to_vec();
+        population.extend(page_number_rates.iter().map(|(rate, _)| *rate));
+        let calibrated = two_cluster_split(&population)
+            .filter(|(blank, filled)| filled - blank >= CONFIG.black_fill.min_cluster_distance)
+            .map(|(blank, filled)| FillStatistics{
+                blank,
+                filled,
+                threshold: (blank + filled) / 2.0,
+            })
+            // 标注填涂率已知的页码点必须被分界正确分开，否则分界不可信
+            .filter(|statistics| {
+                page_number_rates.iter().all(|(rate, expected)| {
+                    (*rate >= statistics.threshold) == (*expected >= CONFIG.black_fill.page_number_filled_rate)
+                })
+            });
+        if let Some(statistics) = calibrated {
+            return statistics;
+        }
+
+        // 使用固定阈值统计
+        let threshold = CONFIG.black_fill.fill_rate_threshold;
+        let (filled, blank): (Vec<f32>, Vec<f32>) = fill_rates.iter().partition(|&&rate| rate >= threshold);
+        // 整页没有空白或没有填涂时使用理想值
+        FillStatistics{
+            blank: if blank.is_empty() { 0.0 } else { blank.iter().sum::<f32>() / blank.len() as f32 },
+            filled: if filled.is_empty() { 1.0 } else { filled.iter().sum::<f32>() / filled.len() as f32 },
+            threshold,
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::config::CONFIG;
+use crate::models::engine_rec::FillStatistics;
+use crate::models::rec_result::{Recognize, Value};
+use crate::models::scan_json::Recognition;
+use crate::recognition::black_fill::RecBlackFill;
+use crate::recognition::engine::Engine;
+
+pub trait RecChoice{
+    /// 选择题整题识别
+    /// 同一题的所有选项互相比较，输出整题答案，并改写每个选项的填涂标记
+    fn rec_choice(&self, rec: &Recognition, fill_rates: &[f32], statistics: &FillStatistics, rec_out: &mut Recognize);
+}
+
+impl RecChoice for Engine {
+    fn rec_choice(&self, rec: &Recognition, fill_rates: &[f32], statistics: &FillStatistics, rec_out: &mut Recognize) {
+        let options_type = rec.options_type.unwrap_or(CONFIG.choice_type.single);
+        let fill_status: Vec<u8> = fill_rates.iter()
+            .map(|&rate| self.black_fill_status(rate, statistics))
+            .collect();
+
+        // 填涂的选项，按选项顺序
+        let filled: Vec<usize> = fill_status.iter().enumerate()
+            .filter(|(_, &status)| status == CONFIG.fill_status.filled)
+            .map(|(index, _)| index)
+            .collect();
+
+        let filled_count = filled.len();
+
+        // 有存疑的选项不猜测答案，交给人工复核
+        rec_out.ambiguous = fill_status.contains(&CONFIG.fill_status.ambiguous);
+
+        let selected: Vec<usize> = match options_type {
+            _ if rec_out.ambiguous => Vec::new(),
+            // 多选题输出所有填涂的选项
+            t if t == CONFIG.choice_type.multiple => filled,
+            // 判断题两个都涂无法判断对错，不输出
+            t if t == CONFIG.choice_type.judge && filled.len() > 1 => Vec::new(),
+            // 单选题输出最黑的选项
+            _ => filled.into_iter()
+                .max_by(|&a, &b| fill_rates[a].total_cmp(&fill_rates[b]))
+                .into_iter()
+                .collect(),
+        };
+
+        for (index, option_out) in rec_out.rec_options.iter_mut().enumerate() {
+            let flag = if selected.contains(&index) { 1 } else { 0 };
+            option_out.value = Some(Value::Integer(flag));
+            option_out.fill_status = fill_status.get(index).copied();
+            option_out.fill_rate = fill_rates.get(index).copied();
+            option_out.confidence = fill_rates.get(index).map(|&rate| self.black_fill_confidence(rate, statistics));
+        }
+
+        // 整题置信度取最不确定的选项
+        let mut confidence = rec_out.rec_options.iter()
+            .filter_map(|option_out| option_out.confidence)
+            .reduce(f32::min);
+        // 单选题涂了多个时，最黑的和第二黑的差距越小越不可信
+        if options_type != CONFIG.choice_type.multiple && filled_count > 1 {
+            let mut filled_rates: Vec<f32> = fill_rates.to_vec();
+            filled_rates.sort_by(|a, b| b.total_cmp(a));
+            let margin = (filled_rates[0] - filled_rates[1]) / (statistics.filled - statistics.blank);
+            confidence = confidence.map(|c| c.min(margin.clamp(0.0, 1.0)));
+        }
+        rec_out.confidence = confidence;
+
+        rec_out.rec_value = match selected.len() {
+            0 => None,
+            // 单个选项保留标注中的值类型
+            1 => rec.options[selected[0]].value.as_ref().map(Value::from),
+            _ => Some(Value::String(
+                selected.iter()
+                    .filter_map(|&index| rec.options[index].value.as_ref())
+                    .map(|value| Value::from(value).to_string())
+                    .collect()
+            )),
+        };
+    }
+}
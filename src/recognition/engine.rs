@@ -4,37 +4,43 @@ use image::{DynamicImage, Rgb};
 use image_base64_wasm::to_base64;
 use imageproc::drawing::draw_filled_circle_mut;
 
-use crate::models::scan_json::{self, InputImage};
+use crate::models::scan_json::{self, Coordinate, InputImage};
 use crate::config::CONFIG;
 
 use crate::models::engine_rec::ReferenceModelPoints;
 use crate::models::rec_result::{OutputRec, PageSize, Value};
-use crate::my_utils::image::{generate_real_coordinate_with_model_points, image_to_base64};
+use crate::my_utils::image::{can_compensate_fold, generate_real_coordinate_with_model_points, image_to_base64};
 use crate::models::engine_rec::ProcessedImagesAndModelPoints;
 use crate::recognition::barcode::RecBarcode;
 use crate::recognition::black_fill::RecBlackFill;
-use crate::recognition::numbers::RecNumber;
+use crate::recognition::choice::RecChoice;
+use crate::recognition::exam_number::RecExamNumber;
+use crate::recognition::numbers::{NumberModel, RecNumber};
 use crate::recognition::vx::RecVX;
 use super::baizheng::Baizheng;
+use super::split::Split;
 
 #[derive(Debug)]
 pub struct Engine {
-    scan_data: scan_json::InputScan
-    // todo
-    // vx_model: torch::onnx,
-    // number_model: torch::onnx,
+    scan_data: scan_json::InputScan,
+    number_model: NumberModel,
 }
 
 impl Engine {
     pub fn new(scan_data: scan_json::InputScan) -> Self {
         Engine {
-            scan_data
+            scan_data,
+            number_model: NumberModel::new(),
         }
     }
     /// 跨模块实现方法的时候访问不到成员变量，需要调用此函数
     pub fn get_scan_data(&self) -> &scan_json::InputScan {
         &self.scan_data
     }
+    /// 手写数字模型
+    pub fn get_number_model(&self) -> &NumberModel {
+        &self.number_model
+    }
     /// 识别，输出第二个变量用于可视化
     pub fn recognize(&self, input_images: &InputImage) -> (OutputRec,  Vec<Option<ProcessedImagesAndModelPoints>>){
         
@@ -42,8 +48,22 @@ impl Engine {
         let scan_data = self.get_scan_data();
         let mut output = OutputRec::new(scan_data);
         
+        // 读图+处理，一张图上有多页的先拆开
+        let (imgs, sources) = self.split_images(input_images);
+
         // 摆正+匹配+找到定位点
-        let imgs_and_model_points = self.baizheng_and_match_page(&input_images, &mut output);
+        let imgs_and_model_points = self.baizheng_and_match_page(imgs, &mut output);
+
+        // 图片序号换回原始输入的序号，并记录每页来自哪张图的哪一份
+        for status in output.images.iter_mut() {
+            let source = &sources[status.input_index];
+            if let Some(page_index) = status.page_index {
+                output.pages[page_index].source = Some(source.clone());
+            }
+            status.part = (source.parts > 1).then_some(source.part);
+            status.duplicate_of = status.duplicate_of.map(|index| sources[index].input_index);
+            status.input_index = source.input_index;
+        }
 
         // 识别
         _recognize(self, &imgs_and_model_points, &mut output);
@@ -63,6 +83,10 @@ fn _recognize(engine: &Engine, imgs_and_model_points: &Vec<Option<ProcessedImage
         let img_and_model_points = img_and_model_points.as_ref().expect("img_and_model_points is None");
         // 填充输出图片信息
         page_out.has_page = true;
+        if let Some(mark_fit) = &img_and_model_points.mark_fit {
+            page_out.location_marks = mark_fit.marks.clone();
+            page_out.location_mark_residual = Some(mark_fit.residual);
+        }
         page_out.image_source = img_and_model_points.img.org.clone();
         page_out.image_rotated = Some(image_to_base64(&img_and_model_points.img.rgb));
         page_out.page_size = Some(
@@ -75,18 +99,52 @@ fn _recognize(engine: &Engine, imgs_and_model_points: &Vec<Option<ProcessedImage
         // 构建坐标转换需要用到的参照定位点
         let reference_model_points = ReferenceModelPoints{
             model_points: &page.model_points_4.expect("model_points_4 is None"),
-            real_model_points: &img_and_model_points.real_model_points
+            real_model_points: &img_and_model_points.real_model_points,
+            model_points_all: &page.model_points,
+            real_model_points_all: &img_and_model_points.real_model_points_all,
+            grid_columns: page.card_columns as usize + 1,
+            folded: img_and_model_points.folded,
         };
-        // 遍历每个option，根据识别类型调用不同的方法
-        for (rec, rec_out) in page.recognizes.iter().zip(page_out.recognizes.iter_mut()){
-            for (option, option_out) in rec.options.iter().zip(rec_out.rec_options.iter_mut()) {
+        // 折过的纸只有真的按网格或按列分别对齐了才算补偿
+        page_out.fold_compensated = img_and_model_points.folded && can_compensate_fold(&reference_model_points);
+        // 整页填涂率统计，得到本页的填涂分界，并用来区分空白、填涂、擦除和存疑
+        // 每个选项的真实坐标，填涂题同时算出填涂率，整页统计和逐题识别共用
+        let real_coordinates: Vec<Vec<Coordinate>> = page.recognizes.iter()
+            .map(|rec| rec.options.iter()
+                .map(|option| generate_real_coordinate_with_model_points(&reference_model_points, &option.coordinate))
+                .collect())
+            .collect();
+        let rec_fill_rates: Vec<Vec<f32>> = page.recognizes.iter().zip(real_coordinates.iter())
+            .map(|(rec, coordinates)| {
+                if rec.rec_type != CONFIG.recognize_type.black_fill {
+                    return Vec::new();
+                }
+                coordinates.iter()
+                    .map(|real_coordinate| engine.black_fill_rate(&img_and_model_points.img, real_coordinate))
+                    .collect()
+            })
+            .collect();
+        let page_fill_rates: Vec<f32> = rec_fill_rates.iter().flatten().copied().collect();
+        // 页码点的标注填涂率已知，作为自适应分界的参照
+        let page_number_rates: Vec<(f32, f32)> = page.page_number_points.iter()
+            .map(|page_number| {
                 let real_coordinate = generate_real_coordinate_with_model_points(
-                    &reference_model_points, &option.coordinate
+                    &reference_model_points, &page_number.coordinate
                 );
-                let mut res:Option<Value> = None;
+                (engine.black_fill_rate(&img_and_model_points.img, &real_coordinate), page_number.fill_rate)
+            })
+            .collect();
+        let fill_statistics = engine.black_fill_statistics(&page_fill_rates, &page_number_rates);
+        // 遍历每个option，根据识别类型调用不同的方法
+        for (rec_index, (rec, rec_out)) in page.recognizes.iter().zip(page_out.recognizes.iter_mut()).enumerate(){
+            // 填涂题的每个选项填涂率，用于整题比较
+            let fill_rates = &rec_fill_rates[rec_index];
+            for (option_index, option_out) in rec_out.rec_options.iter_mut().enumerate() {
+                let real_coordinate = real_coordinates[rec_index][option_index];
+                let mut res:Option<(Value, f32)> = None;
                 match rec.rec_type {
                     rec_type if rec_type==CONFIG.recognize_type.black_fill => {
-                        res = engine.rec_black_fill(&img_and_model_points.img, &real_coordinate);
+                        res = Some(engine.black_fill_value(fill_rates[option_index], &fill_statistics));
                     }
                     rec_type if rec_type==CONFIG.recognize_type.vx => {
                         res = engine.rec_vx(&img_and_model_points.img, &real_coordinate);
@@ -95,17 +153,22 @@ fn _recognize(engine: &Engine, imgs_and_model_points: &Vec<Option<ProcessedImage
                         res = engine.rec_number(&img_and_model_points.img, &real_coordinate);
                     }
                     rec_type if rec_type==CONFIG.recognize_type.qrcode => {
-                        res = engine.rec_barcode(&img_and_model_points.img, &real_coordinate);
+                        res = engine.rec_qrcode(&img_and_model_points.img, &real_coordinate);
                     }
                     rec_type if rec_type==CONFIG.recognize_type.barcode => {
-                        res = engine.rec_barcode(&img_and_model_points.img, &real_coordinate);
+                        let (barcode_res, barcode) = engine.rec_barcode(&img_and_model_points.img, &real_coordinate);
+                        res = barcode_res;
+                        option_out.barcode = barcode;
                     }
                     rec_type if rec_type==CONFIG.recognize_type.coordinate => {
                         option_out.coordinate = Some(real_coordinate);
                     }
                     _ =>{}
                 }
-                option_out.value = res;
+                if let Some((value, confidence)) = res {
+                    option_out.value = Some(value);
+                    option_out.confidence = Some(confidence);
+                }
                 // 渲染
                 draw_filled_circle_mut(&mut render_image, (real_coordinate.x, real_coordinate.y), 5, Rgb([0,0,255]));
                 draw_filled_circle_mut(&mut render_image, (real_coordinate.x+real_coordinate.w, real_coordinate.y+real_coordinate.h), 5, Rgb([0,0,255]));
@@ -115,7 +178,35 @@ fn _recognize(engine: &Engine, imgs_and_model_points: &Vec<Option<ProcessedImage
                 }
                 
             }
+            if rec.rec_type == CONFIG.recognize_type.black_fill {
+                engine.rec_choice(rec, fill_rates, &fill_statistics, rec_out);
+                if rec_out.ambiguous {
+                    output.ambiguous_rec_ids.push(rec.rec_id.clone());
+                }
+            } else {
+                // 整题置信度取最不确定的选项
+                rec_out.confidence = rec_out.rec_options.iter()
+                    .filter_map(|option_out| option_out.confidence)
+                    .reduce(f32::min);
+            }
         }
         page_out.image_rendering = Some(image_to_base64(&render_image));
+        // 考号只出现在一页上
+        if let Some(exam_number) = &page.exam_number {
+            output.exam_number = Some(
+                engine.rec_exam_number(&img_and_model_points.img, &reference_model_points, &fill_statistics, exam_number)
+            );
+        }
+        // 缺考标记
+        if let Some(missing) = &page.missing {
+            for option in missing.options.iter() {
+                let real_coordinate = generate_real_coordinate_with_model_points(
+                    &reference_model_points, &option.coordinate
+                );
+                if matches!(engine.rec_black_fill(&img_and_model_points.img, &real_coordinate, &fill_statistics), Some((Value::Integer(1), _))) {
+                    output.absent = true;
+                }
+            }
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,77 @@
+use crate::config::CONFIG;
+use crate::models::engine_rec::{FillStatistics, ProcessedImages, ReferenceModelPoints};
+use crate::models::rec_result::{ExamNumber, ExamNumberColumn, Value};
+use crate::models::scan_json::Recognition;
+use crate::my_utils::image::generate_real_coordinate_with_model_points;
+use crate::recognition::black_fill::RecBlackFill;
+use crate::recognition::engine::Engine;
+
+pub trait RecExamNumber{
+    /// 考号识别，每列必须且只能填涂一个数字
+    fn rec_exam_number(
+        &self,
+        img: &ProcessedImages,
+        reference_model_points: &ReferenceModelPoints,
+        statistics: &FillStatistics,
+        exam_number: &[Recognition]
+    ) -> ExamNumber;
+}
+
+impl RecExamNumber for Engine {
+    fn rec_exam_number(
+        &self,
+        img: &ProcessedImages,
+        reference_model_points: &ReferenceModelPoints,
+        statistics: &FillStatistics,
+        exam_number: &[Recognition]
+    ) -> ExamNumber {
+        let mut columns = Vec::new();
+        for rec in exam_number{
+            let fill_status: Vec<u8> = rec.options.iter()
+                .map(|option| {
+                    let real_coordinate = generate_real_coordinate_with_model_points(
+                        reference_model_points, &option.coordinate
+                    );
+                    let fill_rate = self.black_fill_rate(img, &real_coordinate);
+                    self.black_fill_status(fill_rate, statistics)
+                })
+                .collect();
+            let filled: Vec<Value> = rec.options.iter().zip(fill_status.iter())
+                .filter(|(_, &status)| status == CONFIG.fill_status.filled)
+                .filter_map(|(option, _)| option.value.as_ref())
+                .map(Value::from)
+                .collect();
+            let column = match filled.len() {
+                _ if fill_status.contains(&CONFIG.fill_status.ambiguous) => ExamNumberColumn{
+                    value: None,
+                    code: CONFIG.exam_number_code.ambiguous,
+                    message: "column has ambiguous mark".to_string(),
+                },
+                0 => ExamNumberColumn{
+                    value: None,
+                    code: CONFIG.exam_number_code.blank,
+                    message: "column is blank".to_string(),
+                },
+                1 => ExamNumberColumn{
+                    value: Some(filled[0].to_string()),
+                    code: CONFIG.exam_number_code.succeeded,
+                    message: "succeeded".to_string(),
+                },
+                _ => ExamNumberColumn{
+                    value: None,
+                    code: CONFIG.exam_number_code.multiple,
+                    message: "column is filled more than once".to_string(),
+                },
+            };
+            columns.push(column);
+        }
+        // 所有列都识别成功才拼接考号
+        let value = columns.iter()
+            .map(|column| column.value.clone())
+            .collect::<Option<String>>();
+        ExamNumber{
+            value,
+            columns,
+        }
+    }
+}
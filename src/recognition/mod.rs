@@ -3,7 +3,11 @@
 
 pub mod baizheng;
 mod black_fill;
+mod choice;
+mod exam_number;
 mod numbers;
 mod vx;
 pub mod engine;
-mod barcode;
\ No newline at end of file
+mod split;
+mod barcode;
+pub mod registry;
@@ -1,13 +1,332 @@
+use std::collections::HashMap;
+
+use image::{GrayImage, Luma};
+use imageproc::region_labelling::{connected_components, Connectivity};
+
+use crate::config::CONFIG;
+use crate::my_utils::image::crop_gray_image;
 use crate::{models::{engine_rec::ProcessedImages, rec_result::Value, scan_json::Coordinate}, recognition::engine::Engine};
 
+/// 数字模板的宽高
+const TEMPLATE_W: usize = 5;
+const TEMPLATE_H: usize = 7;
+
+/// 内置的数字模板，每个数字可以有多种写法
+const DIGIT_TEMPLATES: [(u8, [&str; TEMPLATE_H]); 14] = [
+    (0, [".###.", "#...#", "#...#", "#...#", "#...#", "#...#", ".###."]),
+    (1, ["..#..", ".##..", "..#..", "..#..", "..#..", "..#..", ".###."]),
+    (1, ["...##", "...#.", "..##.", "..#..", ".##..", ".#...", "##..."]),
+    (2, [".###.", "#...#", "....#", "...#.", "..#..", ".#...", "#####"]),
+    (3, ["####.", "....#", "....#", ".###.", "....#", "....#", "####."]),
+    (4, ["...#.", "..##.", ".#.#.", "#..#.", "#####", "...#.", "...#."]),
+    (4, ["#..#.", "#..#.", "#..#.", "#####", "...#.", "...#.", "...#."]),
+    (5, ["#####", "#....", "####.", "....#", "....#", "#...#", ".###."]),
+    (6, ["..##.", ".#...", "#....", "####.", "#...#", "#...#", ".###."]),
+    (7, ["#####", "....#", "...#.", "..#..", "..#..", ".#...", ".#..."]),
+    (7, ["#####", "....#", "....#", "...#.", "...#.", "..#..", "..#.."]),
+    (8, [".###.", "#...#", "#...#", ".###.", "#...#", "#...#", ".###."]),
+    (9, [".###.", "#...#", "#...#", ".####", "....#", "...#.", ".##.."]),
+    (9, [".###.", "#...#", "#...#", ".####", "....#", "....#", "....#"]),
+];
+
+/// 每个数字允许的闭合区域个数，不在其中的加惩罚
+fn expected_holes(digit: u8) -> &'static [usize] {
+    match digit {
+        0 | 6 | 9 => &[1],
+        8 => &[1, 2],
+        2 | 4 => &[0, 1],
+        _ => &[0],
+    }
+}
+
+/// 手写数字分类模型
+/// 把单个数字缩放到模板大小后和内置模板比较，再用闭合区域个数修正
+#[derive(Debug)]
+pub struct NumberModel {
+    templates: Vec<(u8, Vec<f32>)>,
+}
+
+impl NumberModel {
+    pub fn new() -> Self {
+        let templates = DIGIT_TEMPLATES.iter()
+            .map(|(digit, rows)| {
+                let weights = rows.iter()
+                    .flat_map(|row| row.chars().map(|c| if c == '#' { 1.0 } else { 0.0 }))
+                    .collect();
+                (*digit, weights)
+            })
+            .collect();
+        NumberModel{ templates }
+    }
+
+    /// 输出数字和置信度
+    pub fn classify(&self, glyph: &[bool], w: usize, h: usize) -> (u8, f32) {
+        // 很窄的笔画直接认为是1，越窄、笔迹越占满外接框越可信
+        let aspect = w as f32 / h as f32;
+        if aspect < CONFIG.number.narrow_ratio {
+            let fill = glyph.iter().filter(|&&ink| ink).count() as f32 / glyph.len().max(1) as f32;
+            let aspect_margin = 1.0 - aspect / CONFIG.number.narrow_ratio;
+            let fill_margin = (fill - CONFIG.number.narrow_min_fill) / (1.0 - CONFIG.number.narrow_min_fill);
+            return (1, aspect_margin.min(fill_margin).clamp(0.0, 1.0));
+        }
+        let features = resize_glyph(glyph, w, h);
+        let holes = count_holes(glyph, w, h);
+
+        // 每个数字取最接近的模板
+        let mut distances: HashMap<u8, f32> = HashMap::new();
+        for (digit, weights) in self.templates.iter() {
+            let mut distance: f32 = features.iter().zip(weights.iter())
+                .map(|(a, b)| (a - b).powi(2))
+                .sum();
+            if !expected_holes(*digit).contains(&holes) {
+                distance += CONFIG.number.hole_penalty;
+            }
+            let best = distances.entry(*digit).or_insert(f32::MAX);
+            if distance < *best {
+                *best = distance;
+            }
+        }
+        let mut ranked: Vec<(u8, f32)> = distances.into_iter().collect();
+        ranked.sort_by(|a, b| a.1.total_cmp(&b.1));
+        // 和第二名差距越大越可信
+        let (digit, best) = ranked[0];
+        let second = ranked[1].1;
+        (digit, ((second - best) / second.max(f32::EPSILON)).clamp(0.0, 1.0))
+    }
+}
+
+impl Default for NumberModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub trait RecNumber{
     /// 数字识别
-    fn rec_number(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<Value>;
+    /// 输出识别结果和置信度
+    fn rec_number(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<(Value, f32)>;
 }
 
 impl RecNumber for Engine {
-    fn rec_number(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<Value> {
-        None
+    /// 整数输出Value::Integer，带小数点的输出Value::Float，空白输出None
+    fn rec_number(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<(Value, f32)> {
+        // 去掉四周，避开印刷的边框
+        let margin_x = (coordinate.w as f32 * CONFIG.number.border_ignore) as i32;
+        let margin_y = (coordinate.h as f32 * CONFIG.number.border_ignore) as i32;
+        let inner = Coordinate{
+            x: coordinate.x + margin_x,
+            y: coordinate.y + margin_y,
+            w: coordinate.w - 2 * margin_x,
+            h: coordinate.h - 2 * margin_y,
+        };
+        let crop = crop_gray_image(&img.gray, &inner)?;
+
+        let glyphs = segment_glyphs(&crop);
+        if glyphs.is_empty() {
+            return None;
+        }
+        let max_h = glyphs.iter().map(|g| g.h).max().unwrap_or(0);
+        let line_bottom = glyphs.iter().map(|g| g.y + g.h).max().unwrap_or(0);
+
+        let mut text = String::new();
+        let mut confidence: f32 = 1.0;
+        for glyph in glyphs.iter() {
+            // 小数点：又小又靠近底部
+            let is_point = (glyph.h as f32) < max_h as f32 * 0.3
+                && (glyph.w as f32) < max_h as f32 * 0.3
+                && (glyph.y + glyph.h) as f32 > line_bottom as f32 - max_h as f32 * 0.3;
+            if is_point {
+                if !text.is_empty() && !text.contains('.') {
+                    text.push('.');
+                }
+                continue;
+            }
+            let (digit, digit_confidence) = self.get_number_model().classify(&glyph.pixels, glyph.w, glyph.h);
+            text.push((b'0' + digit) as char);
+            confidence = confidence.min(digit_confidence);
+        }
+
+        let text = text.trim_end_matches('.');
+        if text.is_empty() {
+            return None;
+        }
+        if text.contains('.') {
+            return text.parse::<f32>().ok().map(|v| (Value::Float(v), confidence));
+        }
+        text.parse::<i32>().ok().map(|v| (Value::Integer(v), confidence))
+    }
+}
+
+/// 切分出的单个字符
+struct Glyph {
+    y: usize,
+    w: usize,
+    h: usize,
+    /// 外接框内的笔迹
+    pixels: Vec<bool>,
+}
+
+/// 按连通域切分字符，横向重叠的连通域合并成一个字符，按从左到右排序
+fn segment_glyphs(crop: &GrayImage) -> Vec<Glyph> {
+    let (w, h) = (crop.width() as usize, crop.height() as usize);
+    // 笔迹为前景
+    let mut binary = GrayImage::new(w as u32, h as u32);
+    for (x, y, pixel) in crop.enumerate_pixels() {
+        if pixel[0] <= CONFIG.image_process.binarization_threshold {
+            binary.put_pixel(x, y, Luma([255]));
+        }
     }
-}
\ No newline at end of file
+    let ink = binary.pixels().filter(|p| p[0] > 0).count();
+    if (ink as f32) < (w * h) as f32 * CONFIG.number.blank_rate {
+        return Vec::new();
+    }
+    let labels = connected_components(&binary, Connectivity::Eight, Luma([0u8]));
+
+    // 每个连通域的外接框和像素数
+    let mut boxes: HashMap<u32, (usize, usize, usize, usize, usize)> = HashMap::new();
+    for (x, y, label) in labels.enumerate_pixels() {
+        if label[0] == 0 {
+            continue;
+        }
+        let (x, y) = (x as usize, y as usize);
+        let entry = boxes.entry(label[0]).or_insert((x, y, x, y, 0));
+        entry.0 = entry.0.min(x);
+        entry.1 = entry.1.min(y);
+        entry.2 = entry.2.max(x);
+        entry.3 = entry.3.max(y);
+        entry.4 += 1;
+    }
+
+    // 去掉噪点，按x排序
+    let mut groups: Vec<(usize, usize, usize, usize, Vec<u32>)> = boxes.into_iter()
+        .filter(|(_, b)| b.4 >= CONFIG.number.min_component_pixels)
+        .map(|(label, b)| (b.0, b.1, b.2, b.3, vec![label]))
+        .collect();
+    groups.sort_by_key(|g| g.0);
+
+    // 横向重叠超过一半的连通域是同一个字符的断笔
+    let mut merged: Vec<(usize, usize, usize, usize, Vec<u32>)> = Vec::new();
+    for group in groups {
+        if let Some(last) = merged.last_mut() {
+            let overlap = last.2.min(group.2) as i32 - last.0.max(group.0) as i32 + 1;
+            let narrower = (last.2 - last.0 + 1).min(group.2 - group.0 + 1) as i32;
+            if overlap * 2 > narrower {
+                last.0 = last.0.min(group.0);
+                last.1 = last.1.min(group.1);
+                last.2 = last.2.max(group.2);
+                last.3 = last.3.max(group.3);
+                last.4.extend(group.4);
+                continue;
+            }
+        }
+        merged.push(group);
+    }
+
+    merged.into_iter()
+        .map(|(left, top, right, bottom, group_labels)| {
+            let (gw, gh) = (right - left + 1, bottom - top + 1);
+            let mut pixels = vec![false; gw * gh];
+            for y in top..=bottom {
+                for x in left..=right {
+                    let label = labels.get_pixel(x as u32, y as u32)[0];
+                    pixels[(y - top) * gw + (x - left)] = group_labels.contains(&label);
+                }
+            }
+            Glyph{ y: top, w: gw, h: gh, pixels }
+        })
+        .collect()
+}
+
+/// 缩放到模板大小，每格为笔迹占比
+fn resize_glyph(glyph: &[bool], w: usize, h: usize) -> Vec<f32> {
+    let mut features = [0.0f32; TEMPLATE_W * TEMPLATE_H];
+    let mut counts = [0.0f32; TEMPLATE_W * TEMPLATE_H];
+    for y in 0..h {
+        for x in 0..w {
+            let cell = (y * TEMPLATE_H / h) * TEMPLATE_W + x * TEMPLATE_W / w;
+            counts[cell] += 1.0;
+            if glyph[y * w + x] {
+                features[cell] += 1.0;
+            }
+        }
+    }
+    features.iter().zip(counts.iter())
+        .map(|(ink, count)| if *count > 0.0 { (ink / count * 2.0).min(1.0) } else { 0.0 })
+        .collect()
+}
+
+/// 闭合区域个数，即不和边缘连通的空白区域
+fn count_holes(glyph: &[bool], w: usize, h: usize) -> usize {
+    // 四周补一圈空白，保证外部空白连成一片
+    let (pw, ph) = (w + 2, h + 2);
+    let mut background = GrayImage::new(pw as u32, ph as u32);
+    for y in 0..ph {
+        for x in 0..pw {
+            let is_ink = x > 0 && y > 0 && x <= w && y <= h && glyph[(y - 1) * w + (x - 1)];
+            if !is_ink {
+                background.put_pixel(x as u32, y as u32, Luma([255]));
+            }
+        }
+    }
+    let labels = connected_components(&background, Connectivity::Four, Luma([0u8]));
+    let mut sizes: HashMap<u32, usize> = HashMap::new();
+    for label in labels.pixels() {
+        if label[0] > 0 {
+            *sizes.entry(label[0]).or_insert(0) += 1;
+        }
+    }
+    // 去掉外部空白和太小的缝隙
+    let min_size = (w * h / 50).max(2);
+    sizes.values().filter(|&&size| size >= min_size).count().saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{rec_result::Value, scan_json::Coordinate};
+    use crate::my_utils::image::process_dynamic_image;
+    use crate::recognition::engine::Engine;
+    use crate::tests::{draw_strokes, read_json};
+
+    use super::*;
+
+    #[test]
+    fn test_rec_number() {
+        let engine = Engine::new(read_json("dev/test_data/cards/194751/scan.json"));
+        let rec = |img: image::DynamicImage| {
+            let coordinate = Coordinate{x: 0, y: 0, w: img.width() as i32, h: img.height() as i32};
+            let processed = process_dynamic_image(&img, None);
+            engine.rec_number(&processed, &coordinate).map(|(value, _)| value)
+        };
+
+        let twelve = draw_strokes(120, 80, &[
+            &[(30.0, 15.0), (30.0, 65.0)],
+            &[(50.0, 25.0), (60.0, 15.0), (75.0, 15.0), (85.0, 25.0), (83.0, 35.0), (50.0, 65.0), (88.0, 65.0)],
+        ]);
+        assert!(matches!(rec(twelve), Some(Value::Integer(12))));
+
+        let seven_point_five = draw_strokes(120, 80, &[
+            &[(15.0, 15.0), (48.0, 15.0), (30.0, 65.0)],
+            &[(57.0, 63.0), (58.0, 64.0)],
+            &[(102.0, 15.0), (72.0, 15.0), (70.0, 38.0), (90.0, 36.0), (102.0, 48.0), (97.0, 62.0), (70.0, 64.0)],
+        ]);
+        assert!(matches!(rec(seven_point_five), Some(Value::Float(v)) if (v - 7.5).abs() < 1e-4));
+
+        assert!(rec(draw_strokes(120, 80, &[])).is_none());
+    }
+
+    #[test]
+    fn test_classify_narrow() {
+        let model = NumberModel::new();
+        // 实心竖条是1，但不是满分
+        let (digit, bar_confidence) = model.classify(&[true; 3 * 20], 3, 20);
+        assert_eq!(digit, 1);
+        assert!(bar_confidence > 0.5 && bar_confidence < 1.0);
+        // 接近宽度阈值的斜线笔迹稀疏，置信度更低
+        let (w, h) = (6, 20);
+        let slash: Vec<bool> = (0..w * h).map(|i| (i % w) == (w - 1) * (h - 1 - i / w) / (h - 1)).collect();
+        let (digit, slash_confidence) = model.classify(&slash, w, h);
+        assert_eq!(digit, 1);
+        assert!(slash_confidence < bar_confidence);
+        assert_eq!(slash_confidence, 0.0);
+    }
+}
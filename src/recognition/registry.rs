@@ -0,0 +1,85 @@
+//! 多模板识别
+//! 预先加载多份scanjson，按整页上的二维码或条形码内容选择模板，一次处理混合的多场考试答题卡
+
+use std::collections::HashMap;
+
+use crate::models::rec_result::{OutputMultiTemplate, TemplateOutputRec};
+use crate::models::scan_json::{InputImage, InputScan};
+use crate::my_utils::image::trans_base64_to_image;
+
+use super::barcode::decode_page_code;
+use super::engine::Engine;
+
+/// 模板注册表，key为答题卡上码的内容（考试或模板id）
+#[derive(Debug, Default)]
+pub struct TemplateRegistry {
+    engines: HashMap<String, Engine>,
+}
+
+impl TemplateRegistry {
+    pub fn new() -> Self {
+        TemplateRegistry{ engines: HashMap::new() }
+    }
+
+    /// 注册模板，同一个id重复注册时覆盖
+    pub fn register(&mut self, template_id: &str, scan_data: InputScan) {
+        self.engines.insert(template_id.to_string(), Engine::new(scan_data));
+    }
+
+    /// 还没有注册任何模板
+    pub fn is_empty(&self) -> bool {
+        self.engines.is_empty()
+    }
+
+    /// 先整页找码确定每张图片的模板，再按答题卡分组识别
+    /// 每找到一个码开始一份新的答题卡，同一模板的多份答题卡分别识别
+    /// 没有码的图片（如答题卡反面）跟随前一张图片
+    /// 码不在注册表中或前面没有可跟随的图片，记入unmatched_images
+    pub fn recognize(&self, input_images: &InputImage) -> OutputMultiTemplate {
+        let codes: Vec<Option<String>> = input_images.images.iter()
+            .map(|base64_image| decode_page_code(&trans_base64_to_image(base64_image)))
+            .collect();
+        let (groups, unmatched_images) = group_by_code(&codes, |code| self.engines.contains_key(code));
+
+        let results = groups.into_iter()
+            .map(|(template_id, image_indices)| {
+                let group_images = InputImage{
+                    task_id: input_images.task_id.clone(),
+                    images: image_indices.iter().map(|&index| input_images.images[index].clone()).collect(),
+                    calling_type: input_images.calling_type,
+                };
+                let (output, _) = self.engines[&template_id].recognize(&group_images);
+                TemplateOutputRec{ template_id, image_indices, output }
+            })
+            .collect();
+        OutputMultiTemplate{
+            task_id: input_images.task_id.clone(),
+            results,
+            unmatched_images,
+        }
+    }
+}
+
+/// 按每张图片找到的码把图片分成答题卡，输出每份答题卡的模板id和图片序号，以及没有模板的图片序号
+/// known判断码是否在注册表中
+pub fn group_by_code(codes: &[Option<String>], known: impl Fn(&str) -> bool) -> (Vec<(String, Vec<usize>)>, Vec<usize>) {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    let mut unmatched_images = Vec::new();
+    // 当前答题卡在groups中的位置
+    let mut current: Option<usize> = None;
+    for (index, code) in codes.iter().enumerate() {
+        match code {
+            Some(code) if known(code) => {
+                groups.push((code.clone(), Vec::new()));
+                current = Some(groups.len() - 1);
+            }
+            Some(_) => current = None,
+            None => {}
+        }
+        match current {
+            Some(position) => groups[position].1.push(index),
+            None => unmatched_images.push(index),
+        }
+    }
+    (groups, unmatched_images)
+}
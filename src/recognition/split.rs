@@ -0,0 +1,89 @@
+//! 一张图上有多页时拆成单页，在摆正和页匹配之前进行
+
+use image::DynamicImage;
+
+use crate::config::CONFIG;
+use crate::models::engine_rec::ProcessedImages;
+use crate::models::rec_result::ImageSource;
+use crate::models::scan_json::{Coordinate, InputImage, Page};
+use crate::my_utils::image::{crop_image, process_dynamic_image, trans_base64_to_image};
+
+use super::baizheng::{locate_quarter_turns, Location};
+use super::engine::Engine;
+
+pub trait Split{
+    /// 读图并处理成ProcessedImages，一张图上有多页的拆开
+    /// 输出处理后的图片和判断拆分时算出的定位结果，以及每张图片在原始输入中的来源，拆开的每份org都是原图
+    fn split_images(&self, input_images: &InputImage) -> (Vec<(ProcessedImages, Vec<Location>)>, Vec<ImageSource>);
+}
+
+impl Split for Engine {
+    fn split_images(&self, input_images: &InputImage) -> (Vec<(ProcessedImages, Vec<Location>)>, Vec<ImageSource>) {
+        let pages = &self.get_scan_data().pages;
+        let mut imgs = Vec::new();
+        let mut sources = Vec::new();
+        for (input_index, base64_image) in input_images.images.iter().enumerate() {
+            let img = trans_base64_to_image(base64_image);
+            let whole = process_dynamic_image(&img, Some(base64_image.clone()));
+            // 整张图能按模板定位就是一页，不再拆分，定位结果留给摆正使用
+            let locations = locate_quarter_turns(&whole, pages);
+            let parts = if is_located(&locations) {
+                None
+            } else {
+                split_image(pages, &img, base64_image)
+            };
+            let parts = parts.unwrap_or_else(|| vec![(whole, locations)]);
+            let parts_len = parts.len();
+            for (part, part_image) in parts.into_iter().enumerate() {
+                imgs.push(part_image);
+                sources.push(ImageSource{ input_index, parts: parts_len, part });
+            }
+        }
+        (imgs, sources)
+    }
+}
+
+/// 沿长边等分成2到max_parts份，每份的长宽比和模板一致，并且都能按某个page的模板找到定位点时拆分
+/// 输出每份处理后的图片和定位结果，按从左到右或从上到下排列
+fn split_image(pages: &[Page], img: &DynamicImage, base64_image: &str) -> Option<Vec<(ProcessedImages, Vec<Location>)>> {
+    let (w, h) = (img.width() as i32, img.height() as i32);
+    let landscape = w >= h;
+    for parts in 2..=CONFIG.image_split.max_parts as i32 {
+        let part_ratio = if landscape {
+            (w as f32 / parts as f32 / h as f32).ln().abs()
+        } else {
+            (h as f32 / parts as f32 / w as f32).ln().abs()
+        };
+        // 图片可能需要90度旋转，只比较长宽比的大小，不区分横竖
+        let fits_template = pages.iter().any(|page| {
+            let model_ratio = (page.model_size.w as f32 / page.model_size.h as f32).ln().abs();
+            (part_ratio - model_ratio).abs() <= CONFIG.image_split.aspect_tolerance
+        });
+        if !fits_template {
+            continue;
+        }
+        let mut part_images = Vec::new();
+        for part in 0..parts {
+            let coordinate = if landscape {
+                Coordinate{x: w * part / parts, y: 0, w: w * (part + 1) / parts - w * part / parts, h}
+            } else {
+                Coordinate{x: 0, y: h * part / parts, w, h: h * (part + 1) / parts - h * part / parts}
+            };
+            let part_image = process_dynamic_image(&crop_image(img, &coordinate)?, Some(base64_image.to_string()));
+            let locations = locate_quarter_turns(&part_image, pages);
+            if !is_located(&locations) {
+                break;
+            }
+            part_images.push((part_image, locations));
+        }
+        if part_images.len() == parts as usize {
+            return Some(part_images);
+        }
+    }
+    None
+}
+
+/// 至少一个方向能按任一page的模板找到定位点
+fn is_located(locations: &[Location]) -> bool {
+    locations.iter().any(|location| location.corners.is_ok())
+}
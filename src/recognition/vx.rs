@@ -1,12 +1,191 @@
+use image::GrayImage;
+
+use crate::config::CONFIG;
+use crate::my_utils::image::crop_gray_image;
+use crate::my_utils::math::euclidean_distance;
 use crate::{models::{engine_rec::ProcessedImages, rec_result::Value, scan_json::Coordinate}, recognition::engine::Engine};
 
 pub trait RecVX{
     /// 勾叉识别
-    fn rec_vx(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<Value>;
+    /// 输出识别结果和置信度
+    fn rec_vx(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<(Value, f32)>;
 }
 
 impl RecVX for Engine {
-    fn rec_vx(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<Value> {
-        None
+    /// 输出CONFIG.vx_type中的类型
+    fn rec_vx(&self, img: &ProcessedImages, coordinate: &Coordinate) -> Option<(Value, f32)> {
+        // 去掉四周，避开印刷的边框
+        let margin_x = (coordinate.w as f32 * CONFIG.vx.border_ignore) as i32;
+        let margin_y = (coordinate.h as f32 * CONFIG.vx.border_ignore) as i32;
+        let inner = Coordinate{
+            x: coordinate.x + margin_x,
+            y: coordinate.y + margin_y,
+            w: coordinate.w - 2 * margin_x,
+            h: coordinate.h - 2 * margin_y,
+        };
+        let crop = crop_gray_image(&img.gray, &inner)?;
+        let (vx_type, confidence) = classify_vx(&crop);
+        Some((Value::Integer(vx_type as i32), confidence))
+    }
+}
+
+/// 根据笔画形状判断勾、叉、半对和空白
+/// 叉：笔迹外接框的两条对角线都有笔迹
+/// 勾：最低点偏左，从最低点到最左点和右上点各有一笔
+/// 半对：勾的两笔之外还有一段明显的笔迹
+fn classify_vx(crop: &GrayImage) -> (u8, f32) {
+    let (w, h) = (crop.width() as i32, crop.height() as i32);
+    let mut mask = vec![false; (w * h) as usize];
+    let mut ink: Vec<(i32, i32)> = Vec::new();
+    for (x, y, pixel) in crop.enumerate_pixels() {
+        if pixel[0] <= CONFIG.image_process.binarization_threshold {
+            mask[(y as i32 * w + x as i32) as usize] = true;
+            ink.push((x as i32, y as i32));
+        }
+    }
+
+    // 空白
+    let ink_rate = ink.len() as f32 / (w * h) as f32;
+    if ink_rate < CONFIG.vx.blank_rate {
+        return (CONFIG.vx_type.blank, 1.0 - ink_rate / CONFIG.vx.blank_rate);
+    }
+
+    // 笔迹外接框
+    let left = ink.iter().map(|p| p.0).min().unwrap_or(0);
+    let right = ink.iter().map(|p| p.0).max().unwrap_or(0);
+    let top = ink.iter().map(|p| p.1).min().unwrap_or(0);
+    let bottom = ink.iter().map(|p| p.1).max().unwrap_or(0);
+    // 允许笔画偏离直线的距离，和笔迹大小成比例
+    let band = (((right - left).min(bottom - top)) as f32 * 0.08).max(2.0);
+
+    // 叉
+    let cross_score = segment_coverage(&mask, w, h, (left, top), (right, bottom), band)
+        .min(segment_coverage(&mask, w, h, (right, top), (left, bottom), band));
+
+    // 勾的拐点取最底部几行笔迹的中间
+    let bottom_rows = ((bottom - top) / 10).max(1);
+    let bottom_points: Vec<&(i32, i32)> = ink.iter().filter(|p| p.1 >= bottom - bottom_rows).collect();
+    let vertex = (
+        bottom_points.iter().map(|p| p.0).sum::<i32>() / bottom_points.len() as i32,
+        bottom,
+    );
+    let start = *ink.iter().min_by_key(|p| p.0).unwrap_or(&vertex);
+    let end = *ink.iter().max_by_key(|p| p.0 - p.1).unwrap_or(&vertex);
+    let mut tick_shape = segment_coverage(&mask, w, h, start, vertex, band)
+        .min(segment_coverage(&mask, w, h, vertex, end, band));
+    // 拐点应该在左半边
+    let vertex_position = (vertex.0 - left) as f32 / (right - left).max(1) as f32;
+    if vertex_position > 0.6 {
+        tick_shape *= 0.5;
+    }
+    // 两笔之外的笔迹比例，半对会多出一段短斜线
+    let residual = ink.iter()
+        .filter(|&&p| {
+            point_segment_distance(p, start, vertex).min(point_segment_distance(p, vertex, end)) > band * 1.5
+        })
+        .count() as f32 / ink.len() as f32;
+    // 多余笔迹达到阈值时半对得分不低于勾
+    let residual_score = (residual / CONFIG.vx.half_tick_residual * 0.5).clamp(0.0, 1.0);
+    let tick_score = tick_shape * (1.0 - residual_score);
+    let half_tick_score = tick_shape * residual_score;
+
+    [
+        (CONFIG.vx_type.cross, cross_score),
+        (CONFIG.vx_type.tick, tick_score),
+        (CONFIG.vx_type.half_tick, half_tick_score),
+    ]
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((CONFIG.vx_type.blank, 0.0))
+}
+
+/// 线段上有笔迹经过的采样点比例
+fn segment_coverage(mask: &[bool], w: i32, h: i32, from: (i32, i32), to: (i32, i32), band: f32) -> f32 {
+    let length = euclidean_distance((from.0 as f32, from.1 as f32), (to.0 as f32, to.1 as f32));
+    if length < 1.0 {
+        return 0.0;
+    }
+    let samples = length as i32;
+    let radius = band as i32;
+    let mut covered = 0;
+    for i in 0..=samples {
+        let t = i as f32 / samples as f32;
+        let cx = from.0 + ((to.0 - from.0) as f32 * t) as i32;
+        let cy = from.1 + ((to.1 - from.1) as f32 * t) as i32;
+        // 采样点附近有笔迹即算经过
+        let hit = (cy - radius..=cy + radius)
+            .filter(|&y| y >= 0 && y < h)
+            .any(|y| {
+                (cx - radius..=cx + radius)
+                    .filter(|&x| x >= 0 && x < w)
+                    .any(|x| mask[(y * w + x) as usize])
+            });
+        if hit {
+            covered += 1;
+        }
     }
-}
\ No newline at end of file
+    covered as f32 / (samples + 1) as f32
+}
+
+/// 点到线段的距离
+fn point_segment_distance(point: (i32, i32), from: (i32, i32), to: (i32, i32)) -> f32 {
+    let (px, py) = (point.0 as f32, point.1 as f32);
+    let (ax, ay) = (from.0 as f32, from.1 as f32);
+    let (bx, by) = (to.0 as f32, to.1 as f32);
+    let length_squared = (bx - ax).powi(2) + (by - ay).powi(2);
+    if length_squared == 0.0 {
+        return euclidean_distance((px, py), (ax, ay));
+    }
+    let t = (((px - ax) * (bx - ax) + (py - ay) * (by - ay)) / length_squared).clamp(0.0, 1.0);
+    euclidean_distance((px, py), (ax + t * (bx - ax), ay + t * (by - ay)))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+
+    use crate::models::scan_json::Coordinate;
+    use crate::my_utils::image::process_dynamic_image;
+    use crate::tests::{draw_strokes, read_json};
+
+    use super::*;
+
+    const TICK: &[(f32, f32)] = &[(20.0, 40.0), (35.0, 62.0), (75.0, 12.0)];
+
+    #[test]
+    fn test_rec_vx() {
+        let engine = Engine::new(read_json("dev/test_data/cards/194751/scan.json"));
+        let rec = |img: image::DynamicImage| {
+            let coordinate = Coordinate{x: 0, y: 0, w: img.width() as i32, h: img.height() as i32};
+            engine.rec_vx(&process_dynamic_image(&img, None), &coordinate)
+        };
+        let is_type = |res: Option<(Value, f32)>, vx_type: u8| {
+            matches!(res, Some((Value::Integer(value), _)) if value == vx_type as i32)
+        };
+
+        assert!(is_type(rec(draw_strokes(95, 75, &[TICK])), CONFIG.vx_type.tick));
+        let cross = draw_strokes(95, 75, &[&[(20.0, 12.0), (75.0, 62.0)], &[(75.0, 12.0), (20.0, 62.0)]]);
+        assert!(is_type(rec(cross), CONFIG.vx_type.cross));
+        // 勾的长笔画上多一段短斜线
+        let half_tick = draw_strokes(95, 75, &[TICK, &[(48.0, 22.0), (68.0, 48.0)]]);
+        assert!(is_type(rec(half_tick), CONFIG.vx_type.half_tick));
+        assert!(is_type(rec(draw_strokes(95, 75, &[])), CONFIG.vx_type.blank));
+    }
+
+    #[test]
+    fn test_half_tick_boundary() {
+        // 在勾的左上方、离两笔都远的地方补笔迹，使多余笔迹占比刚好在阈值两侧
+        let with_residual = |residual: f32| {
+            let mut crop = draw_strokes(95, 75, &[TICK]).to_luma8();
+            let ink = crop.pixels().filter(|p| p[0] <= CONFIG.image_process.binarization_threshold).count() as f32;
+            let extra = (residual * ink / (1.0 - residual)).round() as u32;
+            for i in 0..extra {
+                crop.put_pixel(40 + i % 11, 12 + i / 11, Luma([0]));
+            }
+            classify_vx(&crop).0
+        };
+        let threshold = CONFIG.vx.half_tick_residual;
+        assert_eq!(with_residual(threshold - 0.02), CONFIG.vx_type.tick);
+        assert_eq!(with_residual(threshold + 0.02), CONFIG.vx_type.half_tick);
+    }
+}